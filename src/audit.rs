@@ -0,0 +1,95 @@
+//! Checking that config files with secrets in them are kept private.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::change::Change;
+use crate::git;
+use crate::loader::{self, Layer};
+use crate::prompt;
+use crate::secret;
+
+/// Keys in `layers` whose values are secrets in plain text: flagged with `"secret": true` or named like a
+/// secret, and not empty or a placeholder. Keys in `protected` (e.g. encrypted ones) are left out.
+pub fn plain_secrets(layers: &[Layer], protected: &[String]) -> Vec<String> {
+    let mut keys = Vec::new();
+    for layer in layers {
+        let Some(object) = layer.value.as_object() else {
+            continue;
+        };
+        for (key, value) in object {
+            if loader::is_reserved(key) || protected.contains(key) || keys.contains(key) {
+                continue;
+            }
+            let (flagged, value) = match loader::definition(value) {
+                Some(definition) => (
+                    definition.get("secret").and_then(Value::as_bool) == Some(true),
+                    &definition[loader::VALUE_KEY],
+                ),
+                None => (false, value),
+            };
+            // `{"$unset": true}` and the like change the environment instead of holding a secret
+            if (!flagged && !secret::looks_secret(key)) || Change::from_value(value).is_some() {
+                continue;
+            }
+            let text = loader::to_env_string(value);
+            if value.is_null() || text.is_empty() || text == prompt::PLACEHOLDER {
+                continue;
+            }
+            keys.push(key.clone());
+        }
+    }
+    keys
+}
+
+/// What is wrong with keeping the secrets `keys` in `file`: it can be read by other users, or it is tracked
+/// by git.
+pub fn problems(file: &str, keys: &[String]) -> Vec<String> {
+    if keys.is_empty() {
+        return Vec::new();
+    }
+    let mut problems = Vec::new();
+    let keys = keys.join(", ");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(file) {
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o044 != 0 {
+                problems.push(format!(
+                    "'{file}' contains secrets ({keys}) but can be read by other users (mode {mode:o}), run `chmod 600 {file}`"
+                ));
+            }
+        }
+    }
+    if git::is_tracked(Path::new(file)) {
+        problems.push(format!(
+            "'{file}' contains secrets ({keys}) and is tracked by git, encrypt them with `json_env encrypt` or move them to a file that is not committed, like .env.local.json"
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Options;
+
+    #[test]
+    fn finds_plain_secrets() {
+        let contents = r#"{
+            "DB_PASSWORD": "hunter2",
+            "API_TOKEN": "TO_BE_OVERWRITTEN",
+            "GITHUB_TOKEN": "",
+            "SIGNING": {"$value": "abc", "secret": true},
+            "DECLARED": {"$value": null, "secret": true},
+            "ENCRYPTED_SECRET": "enc:v1:...",
+            "OLD_PASSWORD": {"$unset": true},
+            "USER": "admin"
+        }"#;
+        let layers = loader::parse_layers("a.json", contents, "$", &Options::default()).unwrap();
+        let keys = plain_secrets(&layers, &["ENCRYPTED_SECRET".to_string()]);
+        assert_eq!(keys, vec!["DB_PASSWORD", "SIGNING"]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Variables that change the inherited environment instead of setting a value: `{"$unset": true}` removes a
+//! variable, `{"$prepend": "./node_modules/.bin", "sep": ":"}` and `{"$append": ...}` add to it.
+
+use std::env;
+
+use serde_json::Value;
+
+use crate::loader::to_env_string;
+
+pub const UNSET_KEY: &str = "$unset";
+pub const PREPEND_KEY: &str = "$prepend";
+pub const APPEND_KEY: &str = "$append";
+
+/// The separator that is used if an object has no `"sep"`, the same as in `PATH`.
+#[cfg(windows)]
+const DEFAULT_SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+const DEFAULT_SEPARATOR: &str = ":";
+
+/// How a variable changes the environment that the program is started in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Change {
+    /// The variable is set to its value.
+    #[default]
+    Set,
+    /// The variable is removed.
+    Unset,
+    /// Parts are added before and after the inherited value of the variable.
+    Extend {
+        before: Vec<String>,
+        after: Vec<String>,
+        sep: String,
+    },
+}
+
+impl Change {
+    /// The change that `value` describes, or `None` if it is not an object with `$unset`, `$prepend` or
+    /// `$append`.
+    pub fn from_value(value: &Value) -> Option<Change> {
+        let object = value.as_object()?;
+        if object
+            .get(UNSET_KEY)
+            .is_some_and(|unset| unset != &Value::Bool(false))
+        {
+            return Some(Change::Unset);
+        }
+        let before = object.get(PREPEND_KEY).map(to_env_string);
+        let after = object.get(APPEND_KEY).map(to_env_string);
+        if before.is_none() && after.is_none() {
+            return None;
+        }
+        let sep = object
+            .get("sep")
+            .map(to_env_string)
+            .unwrap_or_else(|| DEFAULT_SEPARATOR.to_string());
+        Some(Change::Extend {
+            before: before.into_iter().collect(),
+            after: after.into_iter().collect(),
+            sep,
+        })
+    }
+
+    /// Apply this change on top of an earlier definition of the variable with the value `previous`. Returns
+    /// the combined change and the value that the variable gets, given the current environment.
+    pub fn merge(self, key: &str, previous: Option<(&Change, &str)>) -> (Change, String) {
+        let Change::Extend { before, after, sep } = self else {
+            return (self, String::new());
+        };
+        match previous {
+            // The earlier value is the base, not the inherited one
+            Some((Change::Set, value)) => {
+                let value = join(&before, Some(value), &after, &sep);
+                (Change::Set, value)
+            }
+            Some((Change::Unset, _)) => (Change::Set, join(&before, None, &after, &sep)),
+            Some((
+                Change::Extend {
+                    before: earlier_before,
+                    after: earlier_after,
+                    ..
+                },
+                _,
+            )) => {
+                let change = Change::Extend {
+                    before: before.into_iter().chain(earlier_before.clone()).collect(),
+                    after: earlier_after.iter().cloned().chain(after).collect(),
+                    sep,
+                };
+                let value = change.inherited_value(key);
+                (change, value)
+            }
+            None => {
+                let change = Change::Extend { before, after, sep };
+                let value = change.inherited_value(key);
+                (change, value)
+            }
+        }
+    }
+
+    /// The value of `key` after extending what it is in the current environment.
+    fn inherited_value(&self, key: &str) -> String {
+        match self {
+            Change::Extend { before, after, sep } => {
+                join(before, env::var(key).ok().as_deref(), after, sep)
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// `before`, `base` and `after` joined with `sep`. Parts that are already in `base` are not added again, so
+/// that loading the same config file repeatedly does not make the value grow.
+fn join(before: &[String], base: Option<&str>, after: &[String], sep: &str) -> String {
+    let base = base.filter(|base| !base.is_empty());
+    let (before, after) = added(before, base, after, sep);
+    let parts: Vec<&str> = before.into_iter().chain(base).chain(after).collect();
+    parts.join(sep)
+}
+
+fn added<'a>(
+    before: &'a [String],
+    base: Option<&str>,
+    after: &'a [String],
+    sep: &str,
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let existing: Vec<&str> = base
+        .map(|base| base.split(sep).collect())
+        .unwrap_or_default();
+    let new = |parts: &'a [String]| {
+        parts
+            .iter()
+            .map(String::as_str)
+            .filter(|part| !existing.contains(part))
+            .collect::<Vec<_>>()
+    };
+    (new(before), new(after))
+}
+
+/// The shell statement that makes the change to `key` in the current shell, or `None` if there is nothing to
+/// change because all parts are already in it. Values are escaped, only the reference to the inherited value is
+/// expanded by the shell.
+pub fn export_statement(key: &str, change: &Change, value: &str) -> Option<String> {
+    match change {
+        Change::Set => Some(format!("export {key}=\"{}\"", escape(value))),
+        Change::Unset => Some(format!("unset {key}")),
+        Change::Extend { before, after, sep } => {
+            let inherited = env::var(key).ok();
+            let base = inherited.as_deref().filter(|base| !base.is_empty());
+            let (before, after) = added(before, base, after, sep);
+            let (before, after) = (escape(&before.join(sep)), escape(&after.join(sep)));
+            let sep = escape(sep);
+            // `${KEY:+...}` adds the separators only if the variable is set, so that no empty part is added
+            let extended = match (before.is_empty(), after.is_empty()) {
+                (true, true) => return None,
+                (false, true) => format!("{before}${{{key}:+{sep}${key}}}"),
+                (true, false) => format!("${{{key}:+${key}{sep}}}{after}"),
+                (false, false) => format!("{before}${{{key}:+{sep}${key}}}{sep}{after}"),
+            };
+            Some(format!("export {key}=\"{extended}\""))
+        }
+    }
+}
+
+/// `s` with the characters that are special in double quotes escaped.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '"' | '$' | '`' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extend(before: &[&str], after: &[&str]) -> Change {
+        Change::Extend {
+            before: before.iter().map(|s| s.to_string()).collect(),
+            after: after.iter().map(|s| s.to_string()).collect(),
+            sep: ":".to_string(),
+        }
+    }
+
+    #[test]
+    fn merges_changes() {
+        let prepend = Change::from_value(&json!({"$prepend": "./bin", "sep": ":"})).unwrap();
+        assert_eq!(prepend, extend(&["./bin"], &[]));
+        assert_eq!(
+            Change::from_value(&json!({"$unset": true})),
+            Some(Change::Unset)
+        );
+        assert_eq!(Change::from_value(&json!({"type": "console"})), None);
+
+        let (change, value) = prepend.clone().merge("X", Some((&Change::Set, "/usr/bin")));
+        assert_eq!((change, value.as_str()), (Change::Set, "./bin:/usr/bin"));
+        let (change, value) = prepend.clone().merge("X", Some((&Change::Unset, "")));
+        assert_eq!((change, value.as_str()), (Change::Set, "./bin"));
+        let earlier = extend(&["./lib"], &["/opt"]);
+        let (change, _) = prepend.merge("X", Some((&earlier, "")));
+        assert_eq!(change, extend(&["./bin", "./lib"], &["/opt"]));
+
+        assert_eq!(
+            join(&["a".to_string()], Some("a:b"), &["c".to_string()], ":"),
+            "a:b:c"
+        );
+    }
+
+    #[test]
+    fn exports_changes() {
+        let key = "JSON_ENV_CHANGE_TEST_UNSET";
+        assert_eq!(
+            export_statement(key, &extend(&["./bin"], &["/opt"]), ""),
+            Some(format!("export {key}=\"./bin${{{key}:+:${key}}}:/opt\""))
+        );
+        assert_eq!(
+            export_statement(key, &extend(&[], &["/opt"]), ""),
+            Some(format!("export {key}=\"${{{key}:+${key}:}}/opt\""))
+        );
+        assert_eq!(
+            export_statement(key, &Change::Unset, ""),
+            Some(format!("unset {key}"))
+        );
+        assert_eq!(
+            export_statement("A", &Change::Set, "1"),
+            Some("export A=\"1\"".to_string())
+        );
+        assert_eq!(
+            export_statement("A", &Change::Set, r#"a"$(id)`\"#),
+            Some(r#"export A="a\"\$(id)\`\\""#.to_string())
+        );
+        assert_eq!(
+            export_statement(key, &extend(&["$HOME/bin"], &[]), ""),
+            Some(format!("export {key}=\"\\$HOME/bin${{{key}:+:${key}}}\""))
+        );
+    }
+}
@@ -0,0 +1,13 @@
+//! The directory with the configuration of json_env itself, `~/.config/json_env`.
+
+use std::path::PathBuf;
+
+/// The configuration directory, or `None` if the home directory is unknown.
+pub fn path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("json_env"))
+}
+
+/// The file `name` in the configuration directory.
+pub fn file(name: &str) -> Option<PathBuf> {
+    path().map(|directory| directory.join(name))
+}
@@ -0,0 +1,317 @@
+//! Values that are encrypted with [age](https://age-encryption.org), so that secrets can be committed.
+//!
+//! An encrypted value is a string `enc:v1:` followed by the base64 encoded age ciphertext. It is encrypted to
+//! the public keys listed under `"$recipients"` in the root object of its config file, so that everyone whose
+//! private key is in `~/.config/json_env/keys` or `JSON_ENV_PRIVATE_KEY` can decrypt it.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::secrecy::ExposeSecret;
+use age::x25519::{Identity, Recipient};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+
+use crate::config_dir;
+use crate::json;
+use crate::loader::{self, Layer};
+use crate::patch;
+
+/// The start of every encrypted value.
+pub const PREFIX: &str = "enc:v1:";
+
+/// The environment variable that may hold private keys, separated by whitespace.
+pub const KEY_VARIABLE: &str = "JSON_ENV_PRIVATE_KEY";
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypt `plaintext` so that any of `recipients` can decrypt it.
+pub fn encrypt(plaintext: &str, recipients: &[Recipient]) -> Result<String> {
+    let recipients = recipients.iter().map(|r| r as &dyn age::Recipient);
+    let encryptor = age::Encryptor::with_recipients(recipients)?;
+    let mut ciphertext = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut ciphertext)?;
+    writer.write_all(plaintext.as_bytes())?;
+    writer.finish()?;
+    Ok(format!("{PREFIX}{}", STANDARD.encode(ciphertext)))
+}
+
+/// Encrypt `value` as JSON, so that decrypting it gives a value of the same type.
+pub fn encrypt_value(value: &Value, recipients: &[Recipient]) -> Result<String> {
+    encrypt(&serde_json::to_string(value)?, recipients)
+}
+
+/// Decrypt a value that was encrypted with [`encrypt_value`]. Plaintext that is not JSON, e.g. because it was
+/// encrypted with `age` directly, is a string.
+pub fn decrypt_value(value: &str, identities: &[Identity]) -> Result<Value> {
+    let plaintext = decrypt(value, identities)?;
+    Ok(serde_json::from_str(&plaintext).unwrap_or(Value::String(plaintext)))
+}
+
+/// Decrypt a value that starts with [`PREFIX`] with one of `identities`.
+pub fn decrypt(value: &str, identities: &[Identity]) -> Result<String> {
+    let encoded = value
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| anyhow!("the value does not start with '{PREFIX}'"))?;
+    let ciphertext = STANDARD
+        .decode(encoded)
+        .context("the value is not valid base64")?;
+    let decryptor = age::Decryptor::new_buffered(&ciphertext[..])?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|i| i as &dyn age::Identity))?;
+    let mut plaintext = String::new();
+    reader.read_to_string(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+/// The public keys listed under `"$recipients"` in the root object of a config file.
+pub fn recipients(document: &Value) -> Result<Vec<Recipient>> {
+    let Some(listed) = document.get(loader::RECIPIENTS_KEY) else {
+        return Ok(Vec::new());
+    };
+    let listed = listed.as_array().ok_or_else(|| {
+        anyhow!(
+            "\"{}\" must be a list of public keys",
+            loader::RECIPIENTS_KEY
+        )
+    })?;
+    listed
+        .iter()
+        .map(|key| {
+            let key = key.as_str().unwrap_or_default();
+            Recipient::from_str(key).map_err(|e| anyhow!("'{key}' is not a valid public key: {e}"))
+        })
+        .collect()
+}
+
+/// The file that holds the private keys of the user, one per line.
+pub fn keys_file() -> Option<PathBuf> {
+    config_dir::file("keys")
+}
+
+/// The private keys from `JSON_ENV_PRIVATE_KEY` and the keys file. Lines starting with `#` are ignored.
+pub fn identities() -> Result<Vec<Identity>> {
+    let mut text = env::var(KEY_VARIABLE).unwrap_or_default();
+    if let Some(file) = keys_file().filter(|file| file.exists()) {
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Could not read '{}'", file.display()))?;
+        text.push('\n');
+        text.push_str(&contents);
+    }
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|key| Identity::from_str(key).map_err(|e| anyhow!("invalid private key: {e}")))
+        .collect()
+}
+
+/// Create a new private key, append it to `file` and return its public key.
+pub fn generate(file: &Path) -> Result<Recipient> {
+    let identity = Identity::generate();
+    if let Some(directory) = file.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut keys = options.open(file)?;
+    let public = identity.to_public();
+    writeln!(
+        keys,
+        "# public key: {public}\n{}",
+        identity.to_string().expose_secret()
+    )?;
+    Ok(public)
+}
+
+/// Private keys that are only read once a value has to be decrypted.
+#[derive(Default)]
+pub struct Keys {
+    identities: Option<Vec<Identity>>,
+}
+
+impl Keys {
+    /// The keys, read on first use. Fails if there are none.
+    pub fn get(&mut self) -> Result<&[Identity]> {
+        if self.identities.is_none() {
+            let identities = identities()?;
+            if identities.is_empty() {
+                bail!(
+                    "there is no private key in {KEY_VARIABLE} or '{}'",
+                    keys_file().unwrap_or_default().display()
+                );
+            }
+            self.identities = Some(identities);
+        }
+        Ok(self.identities.as_deref().unwrap_or_default())
+    }
+}
+
+impl From<Vec<Identity>> for Keys {
+    fn from(identities: Vec<Identity>) -> Self {
+        Keys {
+            identities: Some(identities),
+        }
+    }
+}
+
+/// Decrypt the encrypted values in `layers`, including the values of definitions. Returns the keys that
+/// were decrypted.
+pub fn decrypt_layers(layers: &mut [Layer], keys: &mut Keys) -> Result<Vec<String>> {
+    let mut decrypted = Vec::new();
+    for layer in layers {
+        let file = layer.file.clone();
+        for (key, value) in loader::values_mut(layer) {
+            let Some(ciphertext) = value.as_str().filter(|v| is_encrypted(v)) else {
+                continue;
+            };
+            *value = keys
+                .get()
+                .and_then(|identities| decrypt_value(ciphertext, identities))
+                .with_context(|| format!("Could not decrypt '{key}' in '{file}'"))?;
+            decrypted.push(key.clone());
+        }
+    }
+    Ok(decrypted)
+}
+
+/// The keys in `layers` whose values are encrypted, including the values of definitions.
+pub fn encrypted_keys(layers: &[Layer]) -> Vec<String> {
+    let mut encrypted = Vec::new();
+    for layer in layers {
+        let Some(object) = layer.value.as_object() else {
+            continue;
+        };
+        for (key, value) in object {
+            let value = match loader::definition(value) {
+                Some(definition) => &definition[loader::VALUE_KEY],
+                None => value,
+            };
+            if value.as_str().is_some_and(is_encrypted) {
+                encrypted.push(key.clone());
+            }
+        }
+    }
+    encrypted
+}
+
+/// Encrypt the values of `names` in the object at `object_path` of a config file, keeping the formatting
+/// of the file. If the file lists no recipients, the user's own public keys are added as `"$recipients"`.
+/// Returns the new contents of the file.
+pub fn encrypt_in(
+    source: &str,
+    object_path: &str,
+    names: &[String],
+    keys: &mut Keys,
+) -> Result<String> {
+    let (mut source, recipients) = recipients_or_own(source, keys)?;
+    for name in names {
+        let value = patch::get(&source, object_path, name)?
+            .ok_or_else(|| anyhow!("'{name}' is not defined in '{object_path}'"))?;
+        if value.as_str().is_some_and(is_encrypted) {
+            continue;
+        }
+        let encrypted = encrypt_value(&value, &recipients)?;
+        source = patch::set(&source, object_path, name, &Value::String(encrypted))?;
+    }
+    Ok(source)
+}
+
+/// The recipients listed in a config file. If there are none, the user's own public keys are added to the
+/// file as `"$recipients"`. Returns the new contents of the file and the recipients.
+pub fn recipients_or_own(source: &str, keys: &mut Keys) -> Result<(String, Vec<Recipient>)> {
+    let listed = recipients(&json::parse(source)?.to_value())?;
+    if !listed.is_empty() {
+        return Ok((source.to_string(), listed));
+    }
+    let own: Vec<Recipient> = keys.get()?.iter().map(Identity::to_public).collect();
+    let listed: Vec<String> = own.iter().map(Recipient::to_string).collect();
+    let source = patch::set(source, "$", loader::RECIPIENTS_KEY, &Value::from(listed))?;
+    Ok((source, own))
+}
+
+/// Replace the encrypted values of `names` in the object at `object_path` by their plaintext.
+pub fn decrypt_in(
+    source: &str,
+    object_path: &str,
+    names: &[String],
+    keys: &mut Keys,
+) -> Result<String> {
+    let mut source = source.to_string();
+    for name in names {
+        let value = patch::get(&source, object_path, name)?
+            .ok_or_else(|| anyhow!("'{name}' is not defined in '{object_path}'"))?;
+        let Some(ciphertext) = value.as_str().filter(|v| is_encrypted(v)) else {
+            continue;
+        };
+        let plaintext = decrypt_value(ciphertext, keys.get()?)
+            .with_context(|| format!("Could not decrypt '{name}'"))?;
+        source = patch::set(&source, object_path, name, &plaintext)?;
+    }
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Options;
+
+    #[test]
+    fn round_trip_with_several_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+        let recipients = [alice.to_public(), bob.to_public()];
+        let encrypted = encrypt("hunter2", &recipients).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            decrypt(&encrypted, std::slice::from_ref(&alice)).unwrap(),
+            "hunter2"
+        );
+        assert_eq!(decrypt(&encrypted, &[eve.clone(), bob]).unwrap(), "hunter2");
+        assert!(decrypt(&encrypted, &[eve]).is_err());
+    }
+
+    #[test]
+    fn decrypts_layers() {
+        let identity = Identity::generate();
+        let encrypted = encrypt("hunter2", &[identity.to_public()]).unwrap();
+        let contents = format!(
+            r#"{{"$recipients": ["{}"], "A": "{encrypted}", "B": {{"$value": "{encrypted}", "secret": true}}, "C": "plain"}}"#,
+            identity.to_public()
+        );
+        let mut layers =
+            loader::parse_layers("a.json", &contents, "$", &Options::default()).unwrap();
+        assert_eq!(recipients(&layers[0].value).unwrap().len(), 1);
+        let mut keys = Keys::from(vec![identity]);
+        let decrypted = decrypt_layers(&mut layers, &mut keys).unwrap();
+        assert_eq!(decrypted, vec!["A", "B"]);
+        assert_eq!(layers[0].value["A"], "hunter2");
+        assert_eq!(layers[0].value["B"]["$value"], "hunter2");
+    }
+
+    #[test]
+    fn encrypts_in_place() {
+        let identity = Identity::generate();
+        let public = identity.to_public().to_string();
+        let mut keys = Keys::from(vec![identity]);
+        let source = "{\n  \"A\": \"a\",\n  \"B\": {\"$value\": 1, \"secret\": true}\n}\n";
+        let names = ["A".to_string(), "B".to_string()];
+        let encrypted = encrypt_in(source, "$", &names, &mut keys).unwrap();
+        assert!(encrypted.contains(&format!("\"$recipients\": [\"{public}\"]")));
+        assert!(encrypted.contains("\"secret\": true"));
+        assert!(!encrypted.contains("\"a\""));
+        let decrypted = decrypt_in(&encrypted, "$", &names, &mut keys).unwrap();
+        let expected =
+            "\"A\": \"a\",\n  \"B\": {\"$value\": 1, \"secret\": true},\n  \"$recipients\"";
+        assert!(decrypted.starts_with(&format!("{{\n  {expected}")));
+    }
+}
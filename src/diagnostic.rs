@@ -0,0 +1,168 @@
+//! Error messages in the style of rustc: a location, the offending line and a caret pointing at it.
+
+use std::fmt::{Display, Formatter};
+
+use crate::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// Where the problem is, e.g. a file name or `-p` for a JSON path given on the command line.
+    pub origin: String,
+    pub line: usize,
+    pub col: usize,
+    /// The full text of the offending line.
+    pub excerpt: String,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// A diagnostic pointing at a byte offset in `source`.
+    pub fn at(level: Level, message: &str, origin: &str, source: &str, offset: usize) -> Self {
+        let (line, col) = json::line_col(source, offset);
+        let excerpt = source.lines().nth(line - 1).unwrap_or("").to_string();
+        Diagnostic {
+            level,
+            message: message.to_string(),
+            origin: origin.to_string(),
+            line,
+            col,
+            excerpt,
+            help: None,
+        }
+    }
+
+    /// Turn a JSON syntax error into a diagnostic, with a hint for the usual suspects.
+    pub fn from_json_error(error: &json::ParseError, file_name: &str, source: &str) -> Self {
+        let mut diagnostic = Diagnostic::at(
+            Level::Error,
+            &format!("invalid JSON: {}", error.message),
+            file_name,
+            source,
+            error.offset,
+        );
+        let rest = &source[error.offset.min(source.len())..];
+        diagnostic.help = if error.message.starts_with("trailing comma") {
+            Some("JSON does not allow a comma after the last element, remove it".to_string())
+        } else if rest.starts_with('\'') {
+            Some("replace the single quotes with double quotes".to_string())
+        } else if rest.starts_with("//") || rest.starts_with("/*") {
+            Some("JSON does not support comments".to_string())
+        } else if error.message.starts_with("expected a key")
+            && rest.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        {
+            Some("keys have to be quoted, e.g. \"KEY\": \"value\"".to_string())
+        } else {
+            None
+        };
+        diagnostic
+    }
+
+    /// Turn the error of the JSON path parser into a diagnostic pointing into the path itself.
+    pub fn from_json_path_error(error: &str, path: &str) -> Self {
+        // The JSON path parser reports positions as ` --> line:col` and what it expected as `= expected ...`.
+        let col = error
+            .split_once("-->")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .and_then(|pos| pos.split_once(':'))
+            .and_then(|(_, col)| col.parse::<usize>().ok())
+            .unwrap_or(1);
+        let expected = error
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("= "))
+            .unwrap_or("invalid syntax");
+        let offset = path
+            .char_indices()
+            .nth(col - 1)
+            .map(|(i, _)| i)
+            .unwrap_or(path.len());
+        let mut diagnostic = Diagnostic::at(
+            Level::Error,
+            &format!("invalid JSON path: {expected}"),
+            "-p",
+            path,
+            offset,
+        );
+        if !path.starts_with('$') {
+            let separator = if path.starts_with(['.', '[']) {
+                ""
+            } else {
+                "."
+            };
+            diagnostic.help = Some(format!(
+                "JSON paths start with '$', e.g. '${separator}{path}'"
+            ));
+        }
+        diagnostic
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{level}: {}", self.message)?;
+        writeln!(f, "{gutter}--> {}:{}:{}", self.origin, self.line, self.col)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {}", self.line, self.excerpt)?;
+        // Tabs are kept so that the caret lines up with the excerpt.
+        let padding: String = self
+            .excerpt
+            .chars()
+            .take(self.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{gutter} | {padding}^")?;
+        if let Some(help) = &self.help {
+            write!(f, "\n{gutter} = help: {help}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn trailing_comma() {
+        let source = "{\n  \"A\": 1,\n}\n";
+        let error = json::parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_json_error(&error, ".env.json", source);
+        assert_eq!((diagnostic.line, diagnostic.col), (2, 9));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: invalid JSON: trailing comma before '}'\n --> .env.json:2:9\n  |\n2 |   \"A\": 1,\n  |         ^\n  = help: JSON does not allow a comma after the last element, remove it"
+        );
+    }
+
+    #[test]
+    fn single_quotes() {
+        let source = "{\"A\": 'b'}";
+        let error = json::parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_json_error(&error, "x.json", source);
+        assert_eq!(diagnostic.col, 7);
+        assert!(diagnostic.help.unwrap().contains("double quotes"));
+    }
+
+    #[test]
+    fn json_path() {
+        let error = jsonpath_rust::JsonPathInst::from_str("$.[").err().unwrap();
+        let diagnostic = Diagnostic::from_json_path_error(&error, "$.[");
+        assert_eq!(diagnostic.col, 4);
+        assert!(diagnostic
+            .message
+            .starts_with("invalid JSON path: expected"));
+    }
+}
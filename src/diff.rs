@@ -0,0 +1,109 @@
+//! Comparing two sets of variables, for `json_env diff`.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::secret;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Added {
+        key: String,
+        value: String,
+    },
+    Removed {
+        key: String,
+        value: String,
+    },
+    Changed {
+        key: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Difference {
+    pub fn key(&self) -> &str {
+        match self {
+            Difference::Added { key, .. }
+            | Difference::Removed { key, .. }
+            | Difference::Changed { key, .. } => key,
+        }
+    }
+
+    /// Replace the values with a mask.
+    pub fn masked(self) -> Difference {
+        let mask = secret::MASK.to_string();
+        match self {
+            Difference::Added { key, .. } => Difference::Added { key, value: mask },
+            Difference::Removed { key, .. } => Difference::Removed { key, value: mask },
+            Difference::Changed { key, .. } => Difference::Changed {
+                key,
+                old: mask.clone(),
+                new: mask,
+            },
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Added { key, value } => write!(f, "+ {key}={value}"),
+            Difference::Removed { key, value } => write!(f, "- {key}={value}"),
+            Difference::Changed { key, old, new } => write!(f, "~ {key}: {old} -> {new}"),
+        }
+    }
+}
+
+/// The differences that turn `old` into `new`, ordered by key.
+pub fn diff(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    for (key, old_value) in old {
+        match new.get(key) {
+            None => differences.push(Difference::Removed {
+                key: key.clone(),
+                value: old_value.clone(),
+            }),
+            Some(new_value) if new_value != old_value => differences.push(Difference::Changed {
+                key: key.clone(),
+                old: old_value.clone(),
+                new: new_value.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (key, value) in new {
+        if !old.contains_key(key) {
+            differences.push(Difference::Added {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    differences.sort_by(|a, b| a.key().cmp(b.key()));
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn added_removed_changed() {
+        let staging = map(&[("A", "1"), ("B", "2"), ("C", "3")]);
+        let prod = map(&[("B", "2"), ("C", "4"), ("D", "5")]);
+        let differences: Vec<String> = diff(&staging, &prod)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(differences, vec!["- A=1", "~ C: 3 -> 4", "+ D=5"]);
+    }
+}
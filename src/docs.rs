@@ -0,0 +1,192 @@
+//! Documentation of all variables, generated by `json_env docs`.
+
+use std::collections::BTreeSet;
+
+use crate::loader::Config;
+use crate::secret;
+
+/// One row of the documentation.
+struct Entry {
+    name: String,
+    description: String,
+    default: String,
+    required: bool,
+    secret: bool,
+    /// The config files that set the variable, in the order in which they are applied.
+    files: Vec<String>,
+    /// The names of the profiles that set the variable.
+    profiles: Vec<String>,
+}
+
+/// A `.env.<name>.json` file next to the config file, and the variables it sets.
+pub struct Profile {
+    pub name: String,
+    pub keys: BTreeSet<String>,
+}
+
+fn entries(config: &Config, profiles: &[Profile]) -> Vec<Entry> {
+    let names: BTreeSet<&String> = config
+        .vars
+        .keys()
+        .chain(config.meta.keys())
+        .chain(profiles.iter().flat_map(|profile| &profile.keys))
+        .collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let meta = config.meta.get(name).cloned().unwrap_or_default();
+            let secret = config.is_secret(name);
+            let mut files: Vec<String> = Vec::new();
+            if let Some(var) = config.vars.get(name) {
+                let sources = var
+                    .overridden
+                    .iter()
+                    .map(|o| &o.source)
+                    .chain(std::iter::once(&var.source));
+                for source in sources {
+                    if !files.contains(&source.file) {
+                        files.push(source.file.clone());
+                    }
+                }
+            } else if let Some(source) = &meta.source {
+                files.push(source.file.clone());
+            }
+            let default = match meta.default {
+                Some(_) if secret => secret::MASK.to_string(),
+                Some(default) => default,
+                None => String::new(),
+            };
+            Entry {
+                name: name.clone(),
+                description: meta.description.unwrap_or_default(),
+                default,
+                required: meta.required,
+                secret,
+                files,
+                profiles: profiles
+                    .iter()
+                    .filter(|profile| profile.keys.contains(name))
+                    .map(|profile| profile.name.clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// A Markdown table of all variables and the `profiles` that set them. Values are never included, only defaults of
+/// variables that are not secret.
+pub fn markdown(config: &Config, profiles: &[Profile]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let code = |text: &str| {
+        if text.is_empty() {
+            String::new()
+        } else {
+            format!("`{}`", cell(text))
+        }
+    };
+    let mut out = String::from("# Environment variables\n\n");
+    out.push_str("| Name | Description | Default | Required | Secret | Defined in | Profiles |\n");
+    out.push_str("|------|-------------|---------|----------|--------|------------|----------|\n");
+    for entry in entries(config, profiles) {
+        let files: Vec<String> = entry.files.iter().map(|f| code(f)).collect();
+        let profiles: Vec<String> = entry.profiles.iter().map(|p| code(p)).collect();
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} |\n",
+            code(&entry.name),
+            cell(&entry.description),
+            code(&entry.default),
+            yes_no(entry.required),
+            yes_no(entry.secret),
+            files.join(", "),
+            profiles.join(", ")
+        ));
+    }
+    out
+}
+
+/// The same table as [`markdown`], as a standalone HTML document.
+pub fn html(config: &Config, profiles: &[Profile]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Environment variables</title></head>\n<body>\n<h1>Environment variables</h1>\n<table>\n",
+    );
+    out.push_str("<tr><th>Name</th><th>Description</th><th>Default</th><th>Required</th><th>Secret</th><th>Defined in</th><th>Profiles</th></tr>\n");
+    for entry in entries(config, profiles) {
+        let files: Vec<String> = entry
+            .files
+            .iter()
+            .map(|f| format!("<code>{}</code>", escape_html(f)))
+            .collect();
+        let profiles: Vec<String> = entry
+            .profiles
+            .iter()
+            .map(|p| format!("<code>{}</code>", escape_html(p)))
+            .collect();
+        out.push_str(&format!(
+            "<tr><td><code>{}</code></td><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&entry.name),
+            escape_html(&entry.description),
+            escape_html(&entry.default),
+            yes_no(entry.required),
+            yes_no(entry.secret),
+            files.join(", "),
+            profiles.join(", ")
+        ));
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_prints_secrets() {
+        let contents = r#"{
+            "DB_PASSWORD": {"$value": "hunter2", "default": "changeme", "secret": true, "required": true},
+            "PORT": {"$value": null, "default": 8080, "description": "HTTP port | TCP"}
+        }"#;
+        let config = Config::test(".env.json", contents);
+        let docs = markdown(&config, &[]);
+        assert!(!docs.contains("hunter2"));
+        assert!(!docs.contains("changeme"));
+        assert!(docs.contains("| `DB_PASSWORD` |  | `****` | yes | yes | `.env.json` |  |"));
+        assert!(docs.contains("| `PORT` | HTTP port \\| TCP | `8080` | no | no | `.env.json` |  |"));
+        assert!(!html(&config, &[]).contains("hunter2"));
+    }
+
+    #[test]
+    fn lists_profiles() {
+        let config = Config::test(
+            ".env.json",
+            r#"{"API_URL": "http://localhost", "PORT": 8080}"#,
+        );
+        let profile = |name: &str, keys: &[&str]| Profile {
+            name: name.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        };
+        let profiles = [
+            profile("prod", &["API_URL", "SENTRY_DSN"]),
+            profile("staging", &["API_URL"]),
+        ];
+        let docs = markdown(&config, &profiles);
+        assert!(docs.contains("| `API_URL` |  |  | no | no | `.env.json` | `prod`, `staging` |"));
+        assert!(docs.contains("| `PORT` |  |  | no | no | `.env.json` |  |"));
+        assert!(docs.contains("| `SENTRY_DSN` |  |  | no | no |  | `prod` |"));
+    }
+}
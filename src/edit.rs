@@ -0,0 +1,356 @@
+//! `json_env edit`: editing a config file with its encrypted values shown in plain text.
+//!
+//! The decrypted file only exists as a temporary file that only the user can read, and is overwritten
+//! before it is removed. When the editor is closed, values that were encrypted are encrypted again, but only
+//! the ones that changed get a new ciphertext, so that unchanged values do not show up in diffs.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+use age::x25519::Recipient;
+use anyhow::{bail, Context, Result};
+use dialoguer::Confirm;
+use serde_json::Value;
+
+use crate::crypt::{self, Keys};
+use crate::diagnostic::Diagnostic;
+use crate::json::{self, Kind, Node, Span};
+use crate::loader;
+
+/// A config file with its encrypted values replaced by their plaintext.
+#[derive(Debug)]
+pub struct Decrypted {
+    pub text: String,
+    /// The plaintext and ciphertext of each encrypted string, by its location in the document.
+    originals: HashMap<Vec<String>, (Value, String)>,
+    /// The `"$recipients"` of the file when it was decrypted.
+    recipients: Option<Value>,
+}
+
+/// Decrypt every encrypted string in a config file, keeping the formatting of the rest.
+pub fn decrypt_all(source: &str, keys: &mut Keys) -> Result<Decrypted> {
+    let document = json::parse(source)?;
+    let mut strings = Vec::new();
+    collect_strings(&document, &mut Vec::new(), &mut strings);
+    let mut originals = HashMap::new();
+    let mut replacements = Vec::new();
+    for (location, span, value) in strings {
+        if !crypt::is_encrypted(&value) {
+            continue;
+        }
+        let plaintext = crypt::decrypt_value(&value, keys.get()?)
+            .with_context(|| format!("Could not decrypt '{}'", location.join(".")))?;
+        replacements.push((span, plaintext.to_string()));
+        originals.insert(location, (plaintext, value));
+    }
+    Ok(Decrypted {
+        text: replace_spans(source, replacements),
+        originals,
+        recipients: document.to_value().get(loader::RECIPIENTS_KEY).cloned(),
+    })
+}
+
+/// Encrypt the values of an edited file that were encrypted before. Values that did not change keep their
+/// ciphertext, unless the recipients changed. Values that are no longer at their location, because their key was
+/// renamed or they moved in an array, are found by their plaintext and encrypted again.
+pub fn encrypt_changed(edited: &str, decrypted: &Decrypted, keys: &mut Keys) -> Result<String> {
+    let document = json::parse(edited)?;
+    let value = document.to_value();
+    let recipients_changed = value.get(loader::RECIPIENTS_KEY) != decrypted.recipients.as_ref();
+    let mut recipients = Recipients {
+        listed: crypt::recipients(&value)?,
+        added: false,
+    };
+    let mut replaced = Vec::new();
+    let mut replacements = Vec::new();
+    let mut missing = Vec::new();
+    for (location, (plaintext, ciphertext)) in &decrypted.originals {
+        let Some(node) = find(&document, location) else {
+            missing.push(plaintext);
+            continue;
+        };
+        let value = node.to_value();
+        let ciphertext = if value == *plaintext && !recipients_changed {
+            ciphertext.clone()
+        } else {
+            recipients.encrypt(&value, keys)?
+        };
+        replaced.push(location.clone());
+        replacements.push((node.span, json::quote(&ciphertext)));
+    }
+    if !missing.is_empty() {
+        let mut nodes = Vec::new();
+        collect_nodes(&document, &mut Vec::new(), &mut nodes);
+        for (location, node) in nodes {
+            // Nodes inside or around a replaced one would overlap with it
+            let overlaps = replaced
+                .iter()
+                .any(|other| other.starts_with(&location) || location.starts_with(other));
+            if overlaps {
+                continue;
+            }
+            let value = node.to_value();
+            if missing.contains(&&value) {
+                replacements.push((node.span, json::quote(&recipients.encrypt(&value, keys)?)));
+                replaced.push(location);
+            }
+        }
+    }
+    let text = replace_spans(edited, replacements);
+    if !recipients.added {
+        return Ok(text);
+    }
+    Ok(crypt::recipients_or_own(&text, keys)?.0)
+}
+
+/// The recipients that changed values are encrypted to. If the file lists none, they are the user's own keys,
+/// which then have to be added to the file.
+struct Recipients {
+    listed: Vec<Recipient>,
+    added: bool,
+}
+
+impl Recipients {
+    fn encrypt(&mut self, value: &Value, keys: &mut Keys) -> Result<String> {
+        if self.listed.is_empty() {
+            self.listed = keys.get()?.iter().map(|i| i.to_public()).collect();
+            self.added = true;
+        }
+        crypt::encrypt_value(value, &self.listed)
+    }
+}
+
+/// Let the user edit `file` in their editor with all values decrypted. Invalid JSON is shown and the editor
+/// is opened again. Returns whether the file was changed.
+pub fn edit(file: &Path, keys: &mut Keys) -> Result<bool> {
+    let source =
+        fs::read_to_string(file).with_context(|| format!("Could not read '{}'", file.display()))?;
+    let decrypted = decrypt_all(&source, keys)?;
+    let temp = TempFile::create(file, &decrypted.text)?;
+    let edited = loop {
+        run_editor(&temp.path)?;
+        let edited = fs::read_to_string(&temp.path)?;
+        match json::parse(&edited) {
+            Ok(_) => break edited,
+            Err(e) => {
+                let origin = file.to_string_lossy();
+                eprintln!("{}", Diagnostic::from_json_error(&e, &origin, &edited));
+                let again = io::stdin().is_terminal()
+                    && Confirm::new()
+                        .with_prompt("The file is not valid JSON. Edit it again?")
+                        .default(true)
+                        .interact()
+                        .unwrap_or(false);
+                if !again {
+                    bail!("'{}' was not changed", file.display());
+                }
+            }
+        }
+    };
+    if edited == decrypted.text {
+        return Ok(false);
+    }
+    let encrypted = encrypt_changed(&edited, &decrypted, keys)?;
+    fs::write(file, encrypted).with_context(|| format!("Could not write '{}'", file.display()))?;
+    Ok(true)
+}
+
+/// Open `path` in `$VISUAL` or `$EDITOR` and wait until it is closed.
+fn run_editor(path: &Path) -> Result<()> {
+    let default = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| default.to_string());
+    // The editor may come with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(default);
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Could not start the editor '{editor}'"))?;
+    if !status.success() {
+        bail!("The editor '{editor}' exited with {status}");
+    }
+    Ok(())
+}
+
+/// Every string value in `node` with its location and span.
+fn collect_strings(
+    node: &Node,
+    location: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, Span, String)>,
+) {
+    match &node.kind {
+        Kind::String(value) => out.push((location.clone(), node.span, value.clone())),
+        Kind::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                location.push(i.to_string());
+                collect_strings(item, location, out);
+                location.pop();
+            }
+        }
+        Kind::Object(members) => {
+            for member in members {
+                location.push(member.key.clone());
+                collect_strings(&member.value, location, out);
+                location.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The node at `location` in `node`, if there is one.
+fn find<'a>(node: &'a Node, location: &[String]) -> Option<&'a Node> {
+    let Some((first, rest)) = location.split_first() else {
+        return Some(node);
+    };
+    let child = match &node.kind {
+        Kind::Array(items) => items.get(first.parse::<usize>().ok()?)?,
+        Kind::Object(members) => &members.iter().rev().find(|m| &m.key == first)?.value,
+        _ => return None,
+    };
+    find(child, rest)
+}
+
+/// Every value in `node` with its location, containers before their contents.
+fn collect_nodes<'a>(
+    node: &'a Node,
+    location: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, &'a Node)>,
+) {
+    out.push((location.clone(), node));
+    match &node.kind {
+        Kind::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                location.push(i.to_string());
+                collect_nodes(item, location, out);
+                location.pop();
+            }
+        }
+        Kind::Object(members) => {
+            for member in members {
+                location.push(member.key.clone());
+                collect_nodes(&member.value, location, out);
+                location.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the text of non-overlapping spans.
+fn replace_spans(source: &str, mut replacements: Vec<(Span, String)>) -> String {
+    replacements.sort_by_key(|(span, _)| span.start);
+    let mut out = String::new();
+    let mut end = 0;
+    for (span, text) in replacements {
+        out.push_str(&source[end..span.start]);
+        out.push_str(&text);
+        end = span.end;
+    }
+    out.push_str(&source[end..]);
+    out
+}
+
+/// A temporary file that only the user can read. It is overwritten before it is removed.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create(original: &Path, contents: &str) -> Result<TempFile> {
+        let name = original
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path = env::temp_dir().join(format!("json_env-{}-{name}", process::id()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Could not create '{}'", path.display()))?;
+        let temp = TempFile { path };
+        file.write_all(contents.as_bytes())?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            if let Ok(mut file) = File::options().write(true).open(&self.path) {
+                let _ = file.write_all(&vec![0; metadata.len() as usize]);
+                let _ = file.sync_all();
+            }
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::x25519::Identity;
+
+    #[test]
+    fn re_encrypts_only_changed_values() {
+        let identity = Identity::generate();
+        let public = identity.to_public();
+        let a = crypt::encrypt("a", std::slice::from_ref(&public)).unwrap();
+        let b = crypt::encrypt("b", std::slice::from_ref(&public)).unwrap();
+        let port = crypt::encrypt_value(&Value::from(8080), std::slice::from_ref(&public)).unwrap();
+        let source = format!(
+            "{{\n  \"A\": \"{a}\",\n  \"B\": {{\"$value\": \"{b}\"}},\n  \"C\": \"c\",\n  \"PORT\": \"{port}\",\n  \"$recipients\": [\"{public}\"]\n}}\n"
+        );
+        let mut keys = Keys::from(vec![identity]);
+        let decrypted = decrypt_all(&source, &mut keys).unwrap();
+        assert!(decrypted
+            .text
+            .starts_with("{\n  \"A\": \"a\",\n  \"B\": {\"$value\": \"b\"},"));
+        assert!(decrypted.text.contains("\"PORT\": 8080,"));
+
+        let edited = decrypted
+            .text
+            .replace("\"b\"", "\"new\"")
+            .replace("\"c\"", "\"d\"")
+            .replace("8080", "9090");
+        let encrypted = encrypt_changed(&edited, &decrypted, &mut keys).unwrap();
+        assert!(encrypted.contains(&a));
+        assert!(!encrypted.contains(&b));
+        assert!(!encrypted.contains(&port) && !encrypted.contains("9090"));
+        assert!(encrypted.contains("\"C\": \"d\""));
+        let again = decrypt_all(&encrypted, &mut keys).unwrap();
+        assert_eq!(again.text, edited);
+    }
+
+    #[test]
+    fn encrypts_renamed_values() {
+        let identity = Identity::generate();
+        let public = identity.to_public();
+        let password =
+            crypt::encrypt_value(&Value::from("hunter2"), std::slice::from_ref(&public)).unwrap();
+        let list = crypt::encrypt_value(&Value::from("b"), std::slice::from_ref(&public)).unwrap();
+        let source = format!(
+            "{{\n  \"DB_PASSWORD\": \"{password}\",\n  \"LIST\": [\"a\", \"{list}\"],\n  \"$recipients\": [\"{public}\"]\n}}\n"
+        );
+        let mut keys = Keys::from(vec![identity]);
+        let decrypted = decrypt_all(&source, &mut keys).unwrap();
+        // The key is renamed and the encrypted item moves to the front of the array
+        let edited = decrypted
+            .text
+            .replace("DB_PASSWORD", "DB_PASS")
+            .replace("[\"a\", \"b\"]", "[\"b\"]");
+        let encrypted = encrypt_changed(&edited, &decrypted, &mut keys).unwrap();
+        assert!(!encrypted.contains("hunter2"));
+        assert!(!encrypted.contains("\"b\""));
+        let again = decrypt_all(&encrypted, &mut keys).unwrap();
+        assert_eq!(again.text, edited);
+    }
+}
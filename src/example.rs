@@ -0,0 +1,147 @@
+//! Example config files with placeholder values, which can be committed instead of the real ones.
+
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use crate::json::{Kind, Member, Node};
+use crate::loader::{self, Layer};
+use crate::resolve;
+
+/// The paths of all keys in the selected objects, e.g. `DB.host` for `{"DB": {"host": "..."}}`.
+/// Definitions (objects with a `$value` key) count as a single key.
+pub fn key_paths(layers: &[Layer]) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    for layer in layers {
+        collect_paths(&layer.value, "", &mut paths);
+    }
+    paths
+}
+
+fn collect_paths(value: &Value, prefix: &str, paths: &mut BTreeSet<String>) {
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    for (key, child) in object {
+        if prefix.is_empty() && loader::is_reserved(key) {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        if child.is_object()
+            && loader::definition(child).is_none()
+            && child.get(resolve::CMD_KEY).is_none()
+        {
+            collect_paths(child, &path, paths);
+        } else {
+            paths.insert(path);
+        }
+    }
+}
+
+/// A copy of a config file with every value replaced, either by an empty string or, if `typed` is set,
+/// by a placeholder of the same type. `"$schema"`, `"$recipients"`, `$cmd` commands and the metadata of definitions
+/// are kept.
+pub fn redact(node: &Node, typed: bool) -> Node {
+    let kind = match &node.kind {
+        Kind::Object(members) => Kind::Object(
+            members
+                .iter()
+                .map(|member| {
+                    let value = if loader::is_reserved(&member.key) || is_command(&member.value) {
+                        member.value.clone()
+                    } else if is_definition(&member.value) {
+                        redact_definition(&member.value, typed)
+                    } else {
+                        redact(&member.value, typed)
+                    };
+                    Member {
+                        value,
+                        ..member.clone()
+                    }
+                })
+                .collect(),
+        ),
+        other => placeholder(other, typed),
+    };
+    Node {
+        kind,
+        span: node.span,
+    }
+}
+
+fn is_command(node: &Node) -> bool {
+    matches!(&node.kind, Kind::Object(members) if members.iter().any(|m| m.key == resolve::CMD_KEY))
+}
+
+fn is_definition(node: &Node) -> bool {
+    matches!(&node.kind, Kind::Object(members) if members.iter().any(|m| m.key == loader::VALUE_KEY))
+}
+
+fn redact_definition(node: &Node, typed: bool) -> Node {
+    let Kind::Object(members) = &node.kind else {
+        return node.clone();
+    };
+    let members = members
+        .iter()
+        .map(|member| {
+            let mut member = member.clone();
+            if member.key == loader::VALUE_KEY && member.value.kind != Kind::Null {
+                member.value.kind = placeholder(&member.value.kind, typed);
+            }
+            member
+        })
+        .collect();
+    Node {
+        kind: Kind::Object(members),
+        span: node.span,
+    }
+}
+
+fn placeholder(kind: &Kind, typed: bool) -> Kind {
+    if !typed {
+        return Kind::String(String::new());
+    }
+    match kind {
+        Kind::Null => Kind::Null,
+        Kind::Bool(_) => Kind::Bool(false),
+        Kind::Number(_) => Kind::Number("0".to_string()),
+        Kind::String(_) => Kind::String("<string>".to_string()),
+        Kind::Array(_) => Kind::Array(Vec::new()),
+        Kind::Object(_) => Kind::Object(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::loader::Options;
+
+    #[test]
+    fn nested_key_paths() {
+        let contents = r#"{"$schema": "s.json", "A": 1, "DB": {"host": "x", "port": 1}, "P": {"$value": "x"}}"#;
+        let layers = loader::parse_layers("a.json", contents, "$", &Options::default()).unwrap();
+        let paths: Vec<String> = key_paths(&layers).into_iter().collect();
+        assert_eq!(paths, vec!["A", "DB.host", "DB.port", "P"]);
+    }
+
+    #[test]
+    fn redacts_values() {
+        let node = json::parse(
+            r#"{"USER": "admin", "PORT": 80, "TLS": true, "DB": {"host": "x"}, "PW": {"$value": "x", "secret": true}}"#,
+        )
+        .unwrap();
+        let typed = redact(&node, true).to_value();
+        assert_eq!(
+            typed,
+            serde_json::json!({"USER": "<string>", "PORT": 0, "TLS": false, "DB": {"host": "<string>"}, "PW": {"$value": "<string>", "secret": true}})
+        );
+        let redacted = redact(&node, false).to_value();
+        assert_eq!(redacted["PORT"], "");
+        assert_eq!(redacted["PW"]["secret"], true);
+    }
+}
@@ -0,0 +1,77 @@
+//! Output of `json_env explain` and `--export --why`.
+
+use serde_json::{json, Value};
+
+use crate::change::Change;
+use crate::loader::{Source, Var};
+use crate::table;
+
+/// A table with one row per definition of each variable, the one that is used first.
+pub fn table<'a>(vars: impl Iterator<Item = (&'a String, &'a Var)>) -> String {
+    let mut rows = Vec::new();
+    for (key, var) in vars {
+        let note = match &var.unexpanded {
+            Some(raw) => format!("expanded from '{raw}'"),
+            None => String::new(),
+        };
+        rows.push(vec![
+            key.clone(),
+            var.shown_value().to_string(),
+            var.source.to_string(),
+            note,
+        ]);
+        for overridden in var.overridden.iter().rev() {
+            rows.push(vec![
+                String::new(),
+                overridden.value.clone(),
+                overridden.source.to_string(),
+                "overridden".to_string(),
+            ]);
+        }
+    }
+    table::render(&["KEY", "VALUE", "SOURCE", "NOTE"], &rows)
+}
+
+pub fn json<'a>(vars: impl Iterator<Item = (&'a String, &'a Var)>) -> Value {
+    let mut out = serde_json::Map::new();
+    for (key, var) in vars {
+        let overridden: Vec<Value> = var
+            .overridden
+            .iter()
+            .map(|o| {
+                let mut entry = source_json(&o.source);
+                entry["value"] = json!(o.value);
+                entry
+            })
+            .collect();
+        let mut entry = source_json(&var.source);
+        entry["value"] = match var.change {
+            Change::Unset => Value::Null,
+            _ => json!(var.value),
+        };
+        entry["unexpanded"] = json!(var.unexpanded);
+        entry["overridden"] = json!(overridden);
+        out.insert(key.clone(), entry);
+    }
+    Value::Object(out)
+}
+
+fn source_json(source: &Source) -> Value {
+    json!({
+        "file": source.file,
+        "json_path": source.json_path,
+        "line": source.line,
+    })
+}
+
+/// A shell comment explaining a variable, printed above its export command by `--why`.
+pub fn comment(key: &str, var: &Var) -> String {
+    let mut out = format!("# {key}: from {}", var.source);
+    if let Some(raw) = &var.unexpanded {
+        out.push_str(&format!(", expanded from '{raw}'"));
+    }
+    for overridden in var.overridden.iter().rev() {
+        out.push_str(&format!(", overrides {}", overridden.source));
+    }
+    out.replace('\n', " ")
+}
@@ -0,0 +1,69 @@
+//! The canonical formatting of config files, used by `json_env fmt`.
+
+use crate::json::{self, Kind, Node, ParseError};
+
+/// How files are formatted.
+#[derive(Debug, Clone)]
+pub struct Style {
+    /// The indentation of one level
+    pub indent: String,
+    /// Sort the keys of every object. Keys that appear more than once keep their order.
+    pub sort_keys: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            indent: "  ".to_string(),
+            sort_keys: false,
+        }
+    }
+}
+
+/// The contents of a JSON file in canonical form: one member or item per line, strings escaped like
+/// serde_json does, and a newline at the end. Numbers are kept as they are written.
+pub fn format(source: &str, style: &Style) -> Result<String, ParseError> {
+    let mut document = json::parse(source)?;
+    if style.sort_keys {
+        sort_keys(&mut document);
+    }
+    Ok(json::to_pretty_string(&document, &style.indent) + "\n")
+}
+
+fn sort_keys(node: &mut Node) {
+    match &mut node.kind {
+        Kind::Object(members) => {
+            members.sort_by(|a, b| a.key.cmp(&b.key));
+            for member in members {
+                sort_keys(&mut member.value);
+            }
+        }
+        Kind::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_form() {
+        let source = "{\"B\":\"\\u0041\\/\", \"A\" : [1.50,{\"y\":1,\"x\":2}],\n\n \"$schema\":\"s.json\", \"E\": {}}";
+        let formatted = format(source, &Style::default()).unwrap();
+        assert_eq!(
+            formatted,
+            "{\n  \"B\": \"A/\",\n  \"A\": [\n    1.50,\n    {\n      \"y\": 1,\n      \"x\": 2\n    }\n  ],\n  \"$schema\": \"s.json\",\n  \"E\": {}\n}\n"
+        );
+        let style = Style {
+            indent: "\t".to_string(),
+            sort_keys: true,
+        };
+        let sorted = format(source, &style).unwrap();
+        assert_eq!(
+            sorted,
+            "{\n\t\"$schema\": \"s.json\",\n\t\"A\": [\n\t\t1.50,\n\t\t{\n\t\t\t\"x\": 2,\n\t\t\t\"y\": 1\n\t\t}\n\t],\n\t\"B\": \"A/\",\n\t\"E\": {}\n}\n"
+        );
+        assert_eq!(format(&sorted, &style).unwrap(), sorted);
+    }
+}
@@ -0,0 +1,137 @@
+//! Finding out whether a file is tracked by git, by reading the index of its repository. Nothing is run and
+//! nothing is fetched.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether `file` is in the index of the git repository it belongs to. Files outside of a repository, and
+/// repositories whose index cannot be read, count as not tracked.
+pub fn is_tracked(file: &Path) -> bool {
+    let Ok(file) = file.canonicalize() else {
+        return false;
+    };
+    let Some((root, git_dir)) = find_repository(&file) else {
+        return false;
+    };
+    let Ok(relative) = file.strip_prefix(&root) else {
+        return false;
+    };
+    let relative: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let relative = relative.join("/");
+    let Ok(index) = fs::read(git_dir.join("index")) else {
+        return false;
+    };
+    index_paths(&index).is_some_and(|paths| paths.contains(&relative))
+}
+
+/// The work tree and the git directory of the repository that contains `file`. In work trees and submodules,
+/// `.git` is a file that points to the git directory.
+fn find_repository(file: &Path) -> Option<(PathBuf, PathBuf)> {
+    for directory in file.ancestors().skip(1) {
+        let dot_git = directory.join(".git");
+        if dot_git.is_dir() {
+            return Some((directory.to_path_buf(), dot_git));
+        }
+        if dot_git.is_file() {
+            let contents = fs::read_to_string(&dot_git).ok()?;
+            let git_dir = contents.trim().strip_prefix("gitdir:")?.trim();
+            return Some((directory.to_path_buf(), directory.join(git_dir)));
+        }
+    }
+    None
+}
+
+/// The paths in a git index file of version 2, 3 or 4, or `None` if it is not one.
+/// See <https://git-scm.com/docs/index-format>.
+fn index_paths(index: &[u8]) -> Option<Vec<String>> {
+    if index.get(0..4)? != b"DIRC" {
+        return None;
+    }
+    let version = u32::from_be_bytes(index.get(4..8)?.try_into().ok()?);
+    let count = u32::from_be_bytes(index.get(8..12)?.try_into().ok()?);
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let mut paths = Vec::new();
+    let mut previous: Vec<u8> = Vec::new();
+    let mut pos = 12;
+    for _ in 0..count {
+        let start = pos;
+        // Ten 32 bit stat fields and the object id, followed by 16 bits of flags
+        pos += 40 + 20;
+        let flags = u16::from_be_bytes(index.get(pos..pos + 2)?.try_into().ok()?);
+        pos += 2;
+        if version >= 3 && flags & 0x4000 != 0 {
+            pos += 2;
+        }
+        let path = if version == 4 {
+            // The path is stored as the number of bytes to remove from the previous path, and a suffix
+            let (strip, len) = varint(index.get(pos..)?)?;
+            pos += len;
+            let end = pos + index.get(pos..)?.iter().position(|b| *b == 0)?;
+            let mut path = previous.get(..previous.len().checked_sub(strip)?)?.to_vec();
+            path.extend_from_slice(&index[pos..end]);
+            pos = end + 1;
+            path
+        } else {
+            let end = pos + index.get(pos..)?.iter().position(|b| *b == 0)?;
+            let path = index[pos..end].to_vec();
+            // Entries are padded with one to eight NUL bytes to a multiple of eight bytes
+            pos = start + (end - start + 8) / 8 * 8;
+            path
+        };
+        paths.push(String::from_utf8_lossy(&path).to_string());
+        previous = path;
+    }
+    Some(paths)
+}
+
+/// A variable length integer as git writes it in version 4 indexes, and the number of bytes it takes.
+fn varint(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut len = 1;
+    let mut byte = *bytes.first()?;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        byte = *bytes.get(len)?;
+        len += 1;
+        value = ((value + 1) << 7) + (byte & 0x7f) as usize;
+    }
+    Some((value, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> Vec<u8> {
+        let mut entry = vec![0; 60];
+        entry.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        entry.extend_from_slice(path.as_bytes());
+        let padding = 8 - entry.len() % 8;
+        entry.extend(std::iter::repeat_n(0, padding));
+        entry
+    }
+
+    #[test]
+    fn reads_index() {
+        let mut index = b"DIRC".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend(entry(".env.json"));
+        index.extend(entry("config/.env.prod.json"));
+        assert_eq!(
+            index_paths(&index).unwrap(),
+            vec![".env.json", "config/.env.prod.json"]
+        );
+        assert_eq!(index_paths(b"not an index"), None);
+    }
+
+    #[test]
+    fn reads_varints() {
+        assert_eq!(varint(&[0x05]), Some((5, 1)));
+        assert_eq!(varint(&[0x80, 0x00]), Some((128, 2)));
+    }
+}
@@ -0,0 +1,65 @@
+//! Replacing references like `$NAME` and `${NAME}` in strings with the values of variables.
+
+/// Replace `$NAME` and `${NAME}` in `text` with the value that `lookup` returns for `NAME`. Names consist of
+/// letters, digits and underscores, `$NAME` takes the longest one. References that `lookup` does not know are
+/// kept as they are.
+pub fn interpolate(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) if is_name(&braced[..end]) => (&braced[..end], end + 2),
+                _ => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match (!name.is_empty()).then(|| lookup(name)).flatten() {
+            Some(value) => {
+                out.push_str(&value);
+                rest = &after[len..];
+            }
+            None => {
+                out.push('$');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_references() {
+        let lookup = |name: &str| match name {
+            "USER" => Some("carl".to_string()),
+            "PORT" => Some("8080".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            interpolate("$USER@localhost:${PORT}/$USER", lookup),
+            "carl@localhost:8080/carl"
+        );
+        assert_eq!(interpolate("${PORT}0 $PORT0", lookup), "80800 $PORT0");
+        assert_eq!(
+            interpolate("$ ${ $MISSING ${USER", lookup),
+            "$ ${ $MISSING ${USER"
+        );
+        assert_eq!(interpolate("price: 5$", lookup), "price: 5$");
+    }
+}
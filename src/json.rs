@@ -0,0 +1,567 @@
+//! A small JSON parser that keeps track of where things are in the source.
+//!
+//! `serde_json` is used for everything that only cares about values, but it forgets
+//! positions and silently keeps the last of two identical keys. The [`Node`] tree
+//! produced here keeps every member of an object, in order, together with its byte span.
+
+use std::fmt::{Display, Formatter};
+
+use serde_json::{Map, Number, Value};
+
+/// A byte range in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Null,
+    Bool(bool),
+    /// Numbers are kept as written, so that they can be handed to `serde_json` unchanged.
+    Number(String),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<Member>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: Kind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub key: String,
+    pub key_span: Span,
+    pub value: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte offset at which the error was detected.
+    pub offset: usize,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Two members of the same object whose keys are identical, or identical apart from case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey {
+    pub first: String,
+    pub first_span: Span,
+    pub second: String,
+    pub second_span: Span,
+    /// `true` if the keys only match when case is ignored.
+    pub case_only: bool,
+}
+
+/// Parse a complete JSON document.
+pub fn parse(source: &str) -> Result<Node, ParseError> {
+    let mut parser = Parser {
+        src: source,
+        bytes: source.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let node = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("trailing characters after the JSON document"));
+    }
+    Ok(node)
+}
+
+/// 1-based line and column of a byte offset. Columns count characters, not bytes.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+impl Node {
+    /// Convert into a `serde_json` value. Like `serde_json`, the last of several identical keys wins. Numbers
+    /// were checked while parsing, so they can always be converted.
+    pub fn to_value(&self) -> Value {
+        match &self.kind {
+            Kind::Null => Value::Null,
+            Kind::Bool(b) => Value::Bool(*b),
+            Kind::Number(n) => serde_json::from_str::<Number>(n)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            Kind::String(s) => Value::String(s.clone()),
+            Kind::Array(items) => Value::Array(items.iter().map(Node::to_value).collect()),
+            Kind::Object(members) => {
+                let mut map = Map::new();
+                for member in members {
+                    map.insert(member.key.clone(), member.value.to_value());
+                }
+                Value::Object(map)
+            }
+        }
+    }
+
+    /// Find all keys that appear more than once in the same object, anywhere in the tree.
+    pub fn duplicate_keys(&self) -> Vec<DuplicateKey> {
+        let mut found = Vec::new();
+        self.collect_duplicates(&mut found);
+        found
+    }
+
+    fn collect_duplicates(&self, found: &mut Vec<DuplicateKey>) {
+        match &self.kind {
+            Kind::Array(items) => {
+                for item in items {
+                    item.collect_duplicates(found);
+                }
+            }
+            Kind::Object(members) => {
+                for (i, second) in members.iter().enumerate() {
+                    // An identical key is an error, so it is reported even if a key that only differs in case
+                    // comes in between
+                    let earlier = members[..i]
+                        .iter()
+                        .rev()
+                        .find(|first| first.key == second.key)
+                        .or_else(|| {
+                            members[..i]
+                                .iter()
+                                .rev()
+                                .find(|first| first.key.to_lowercase() == second.key.to_lowercase())
+                        });
+                    if let Some(first) = earlier {
+                        found.push(DuplicateKey {
+                            first: first.key.clone(),
+                            first_span: first.key_span,
+                            second: second.key.clone(),
+                            second_span: second.key_span,
+                            case_only: first.key != second.key,
+                        });
+                    }
+                }
+                for member in members {
+                    member.value.collect_duplicates(found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Print a node as indented JSON, keeping the order of object members.
+pub fn to_pretty_string(node: &Node, indent: &str) -> String {
+    let mut out = String::new();
+    write_pretty(node, indent, 0, &mut out);
+    out
+}
+
+fn write_pretty(node: &Node, indent: &str, level: usize, out: &mut String) {
+    let newline = |out: &mut String, level: usize| {
+        out.push('\n');
+        out.push_str(&indent.repeat(level));
+    };
+    match &node.kind {
+        Kind::Null => out.push_str("null"),
+        Kind::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Kind::Number(n) => out.push_str(n),
+        Kind::String(s) => out.push_str(&quote(s)),
+        Kind::Array(items) if items.is_empty() => out.push_str("[]"),
+        Kind::Object(members) if members.is_empty() => out.push_str("{}"),
+        Kind::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, level + 1);
+                write_pretty(item, indent, level + 1, out);
+            }
+            newline(out, level);
+            out.push(']');
+        }
+        Kind::Object(members) => {
+            out.push('{');
+            for (i, member) in members.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, level + 1);
+                out.push_str(&quote(&member.key));
+                out.push_str(": ");
+                write_pretty(&member.value, indent, level + 1, out);
+            }
+            newline(out, level);
+            out.push('}');
+        }
+    }
+}
+
+/// A JSON string literal with the escapes `serde_json` uses.
+pub fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("\"{s}\""))
+}
+
+/// How deeply arrays and objects may be nested, the same limit as in `serde_json`.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    /// The number of arrays and objects that the parser is in.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            offset: self.pos,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        let kind = match self.peek() {
+            None => return Err(self.error("unexpected end of file, expected a value")),
+            Some(b'{' | b'[') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(&format!(
+                        "arrays and objects are nested more than {MAX_DEPTH} levels deep"
+                    )));
+                }
+                self.depth += 1;
+                let kind = match self.peek() {
+                    Some(b'{') => self.object(),
+                    _ => self.array(),
+                };
+                self.depth -= 1;
+                kind?
+            }
+            Some(b'"') => Kind::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(b't') => self.literal("true", Kind::Bool(true))?,
+            Some(b'f') => self.literal("false", Kind::Bool(false))?,
+            Some(b'n') => self.literal("null", Kind::Null)?,
+            Some(b'\'') => return Err(self.error("strings must use double quotes")),
+            Some(_) => return Err(self.error("expected a value")),
+        };
+        Ok(Node {
+            kind,
+            span: Span {
+                start,
+                end: self.pos,
+            },
+        })
+    }
+
+    fn literal(&mut self, word: &str, kind: Kind) -> Result<Kind, ParseError> {
+        if self.src[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(kind)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn object(&mut self) -> Result<Kind, ParseError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Kind::Object(members));
+        }
+        let mut comma = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'"') => {}
+                Some(b'}') if !members.is_empty() => {
+                    return Err(ParseError {
+                        message: "trailing comma before '}'".to_string(),
+                        offset: comma,
+                    });
+                }
+                Some(b'\'') => return Err(self.error("keys must use double quotes")),
+                None => return Err(self.error("unexpected end of file inside an object")),
+                Some(_) => return Err(self.error("expected a key in double quotes")),
+            }
+            let key_start = self.pos;
+            let key = self.string()?;
+            let key_span = Span {
+                start: key_start,
+                end: self.pos,
+            };
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected ':' after the key"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let value = self.value()?;
+            members.push(Member {
+                key,
+                key_span,
+                value,
+            });
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => {
+                    comma = self.pos;
+                    self.pos += 1;
+                }
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Kind::Object(members));
+                }
+                None => return Err(self.error("unexpected end of file inside an object")),
+                Some(_) => return Err(self.error("expected ',' or '}' after a value")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Kind, ParseError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Kind::Array(items));
+        }
+        let mut comma = 0;
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(b']') {
+                return Err(ParseError {
+                    message: "trailing comma before ']'".to_string(),
+                    offset: comma,
+                });
+            }
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => {
+                    comma = self.pos;
+                    self.pos += 1;
+                }
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Kind::Array(items));
+                }
+                None => return Err(self.error("unexpected end of file inside an array")),
+                Some(_) => return Err(self.error("expected ',' or ']' after a value")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Kind, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit after the decimal point"));
+            }
+            self.digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+            self.digits();
+        }
+        let number = &self.src[start..self.pos];
+        if serde_json::from_str::<Number>(number).is_err() {
+            return Err(ParseError {
+                message: "number out of range".to_string(),
+                offset: start,
+            });
+        }
+        Ok(Kind::Number(number.to_string()))
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.src[self.pos..].chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    out.push(self.escape()?);
+                }
+                '\n' => return Err(self.error("unterminated string")),
+                c if (c as u32) < 0x20 => {
+                    return Err(self.error("control characters must be escaped in strings"));
+                }
+                c => {
+                    out.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.hex4()?;
+                if (0xD800..0xDC00).contains(&high) {
+                    if !self.src[self.pos..].starts_with("\\u") {
+                        return Err(self.error("unpaired surrogate in \\u escape"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(self.error("unpaired surrogate in \\u escape"));
+                    }
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    return char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"));
+                }
+                return char::from_u32(high).ok_or_else(|| self.error("invalid \\u escape"));
+            }
+            _ => return Err(self.error("invalid escape sequence")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_serde_json() {
+        let src = include_str!("../examples/nested/.env.json");
+        let node = parse(src).unwrap();
+        assert_eq!(node.to_value(), serde_json::from_str::<Value>(src).unwrap());
+
+        let escapes = r#"{"a": "é😀\n", "b": [1.5e3, -0, true, null]}"#;
+        assert_eq!(
+            parse(escapes).unwrap().to_value(),
+            serde_json::from_str::<Value>(escapes).unwrap()
+        );
+    }
+
+    #[test]
+    fn finds_duplicate_keys() {
+        let src = "{\n  \"API_KEY\": \"a\",\n  \"nested\": {\"x\": 1, \"x\": 2},\n  \"Api_Key\": \"b\"\n}";
+        let dups = parse(src).unwrap().duplicate_keys();
+        assert_eq!(dups.len(), 2);
+        let case_only = dups.iter().find(|d| d.case_only).unwrap();
+        assert_eq!(case_only.first, "API_KEY");
+        assert_eq!(line_col(src, case_only.first_span.start), (2, 3));
+        assert_eq!(line_col(src, case_only.second_span.start), (4, 3));
+        assert!(dups.iter().any(|d| !d.case_only && d.first == "x"));
+    }
+
+    #[test]
+    fn prefers_identical_duplicates() {
+        let dups = parse(r#"{"a": 1, "A": 2, "a": 3}"#)
+            .unwrap()
+            .duplicate_keys();
+        assert_eq!(dups.len(), 2);
+        assert!(dups[0].case_only);
+        assert!(!dups[1].case_only);
+        assert_eq!((dups[1].first.as_str(), dups[1].first_span.start), ("a", 1));
+    }
+
+    #[test]
+    fn rejects_deep_nesting_and_huge_numbers() {
+        let deep = "[".repeat(200_000);
+        assert!(parse(&deep).unwrap_err().message.contains("nested"));
+        let limit = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse(&limit).is_ok());
+        let err = parse(r#"{"BIG": 1e400}"#).unwrap_err();
+        assert_eq!(
+            (err.message.as_str(), err.offset),
+            ("number out of range", 8)
+        );
+    }
+
+    #[test]
+    fn pretty_keeps_order() {
+        let node = parse(r#"{"b": [1, {}], "a": {"c": "x\"y"}, "e": []}"#).unwrap();
+        assert_eq!(
+            to_pretty_string(&node, "  "),
+            "{\n  \"b\": [\n    1,\n    {}\n  ],\n  \"a\": {\n    \"c\": \"x\\\"y\"\n  },\n  \"e\": []\n}"
+        );
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let err = parse("{\n  \"a\": 1,\n}").unwrap_err();
+        // The error points at the comma, not at the '}' on the next line
+        assert_eq!(line_col("{\n  \"a\": 1,\n}", err.offset), (2, 9));
+        let err = parse("[1, 2,\n]").unwrap_err();
+        assert_eq!(err.offset, 5);
+        assert!(err.message.contains("trailing comma"));
+    }
+}
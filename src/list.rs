@@ -0,0 +1,87 @@
+//! Output of `json_env list`.
+
+use serde_json::{json, Value};
+
+use crate::change::Change;
+use crate::loader::{Source, Var};
+use crate::table;
+
+/// A table with the key, value and source of each variable.
+pub fn table<'a>(vars: impl Iterator<Item = (&'a String, &'a Var)>) -> String {
+    let rows: Vec<Vec<String>> = vars
+        .map(|(key, var)| {
+            vec![
+                key.clone(),
+                var.shown_value().to_string(),
+                location(&var.source),
+            ]
+        })
+        .collect();
+    table::render(&["KEY", "VALUE", "SOURCE"], &rows)
+}
+
+/// An object with the value and source file of each variable, the value is `null` if it is unset.
+pub fn json<'a>(vars: impl Iterator<Item = (&'a String, &'a Var)>) -> Value {
+    let out = vars
+        .map(|(key, var)| {
+            let value = match var.change {
+                Change::Unset => Value::Null,
+                _ => json!(var.value),
+            };
+            let entry = json!({
+                "value": value,
+                "file": var.source.file,
+                "line": var.source.line,
+            });
+            (key.clone(), entry)
+        })
+        .collect();
+    Value::Object(out)
+}
+
+/// `KEY=value` lines, without sources.
+pub fn plain<'a>(vars: impl Iterator<Item = (&'a String, &'a Var)>) -> String {
+    vars.map(|(key, var)| format!("{key}={}\n", var.shown_value()))
+        .collect()
+}
+
+fn location(source: &Source) -> String {
+    match source.line {
+        Some(line) => format!("{}:{line}", source.file),
+        None => source.file.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Vars;
+
+    #[test]
+    fn formats() {
+        let mut vars = Vars::new();
+        for (key, value, line) in [("A", "1", Some(2)), ("LONG_NAME", "x", None)] {
+            vars.insert(key.to_string(), Var::test(value, ".env.json", line));
+        }
+        assert_eq!(
+            table(vars.iter()),
+            "KEY        VALUE  SOURCE\nA          1      .env.json:2\nLONG_NAME  x      .env.json\n"
+        );
+        assert_eq!(plain(vars.iter()), "A=1\nLONG_NAME=x\n");
+        assert_eq!(json(vars.iter())["A"]["line"], 2);
+    }
+
+    #[test]
+    fn marks_unset_variables() {
+        let mut vars = Vars::new();
+        let mut var = Var::test("", ".env.json", Some(2));
+        var.change = Change::Unset;
+        vars.insert("DB_PASSWORD".to_string(), var.masked());
+        assert_eq!(
+            table(vars.iter()),
+            "KEY          VALUE    SOURCE\nDB_PASSWORD  <unset>  .env.json:2\n"
+        );
+        assert_eq!(plain(vars.iter()), "DB_PASSWORD=<unset>\n");
+        assert_eq!(json(vars.iter())["DB_PASSWORD"]["value"], Value::Null);
+    }
+}
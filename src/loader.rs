@@ -0,0 +1,802 @@
+//! Reading config files and merging them into the variables that are passed to the child process.
+//!
+//! Every variable remembers where it was defined and which earlier definitions it replaced,
+//! so that `json_env explain` can tell why a variable ended up with its value.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use jsonpath_rust::{JsonPathFinder, JsonPathInst, JsonPathValue};
+use serde_json::Value;
+
+use crate::audit;
+use crate::change::Change;
+use crate::crypt;
+use crate::diagnostic::{Diagnostic, Level};
+use crate::interpolate::interpolate;
+use crate::json;
+use crate::resolve::Resolvers;
+use crate::secret;
+
+/// Where a value was defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub file: String,
+    pub json_path: String,
+    pub line: Option<usize>,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line} ({})", self.file, self.json_path),
+            None => write!(f, "{} ({})", self.file, self.json_path),
+        }
+    }
+}
+
+/// A definition that was replaced by a later one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overridden {
+    pub value: String,
+    pub source: Source,
+}
+
+/// How `list` and `explain` show the value of a variable that is unset.
+pub const UNSET_VALUE: &str = "<unset>";
+
+/// A variable after merging all config files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Var {
+    pub value: String,
+    pub source: Source,
+    /// The value as written in the file, if expansion changed it.
+    pub unexpanded: Option<String>,
+    /// Earlier definitions of the same key, oldest first.
+    pub overridden: Vec<Overridden>,
+    /// How the variable changes the inherited environment.
+    pub change: Change,
+}
+
+impl Var {
+    /// A copy with all values replaced by a mask. Variables that are unset have no value to mask.
+    pub fn masked(&self) -> Var {
+        let mut var = self.clone();
+        if var.change != Change::Unset {
+            var.value = secret::MASK.to_string();
+        }
+        var.unexpanded = var.unexpanded.map(|_| secret::MASK.to_string());
+        for overridden in &mut var.overridden {
+            overridden.value = secret::MASK.to_string();
+        }
+        var
+    }
+
+    /// The value as it is shown to the user, `<unset>` if the variable is removed from the environment.
+    pub fn shown_value(&self) -> &str {
+        match self.change {
+            Change::Unset => UNSET_VALUE,
+            _ => &self.value,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Var {
+    /// A variable that is set to `value` at `line` of `file`.
+    pub fn test(value: &str, file: &str, line: Option<usize>) -> Var {
+        Var {
+            value: value.to_string(),
+            source: Source {
+                file: file.to_string(),
+                json_path: "$".to_string(),
+                line,
+            },
+            unexpanded: None,
+            overridden: Vec::new(),
+            change: Change::Set,
+        }
+    }
+}
+
+pub type Vars = BTreeMap<String, Var>;
+
+/// The source of variables that are taken from the environment instead of a config file.
+pub const ENVIRONMENT: &str = "<environment>";
+
+/// Key that marks an object as the definition of a variable, e.g. `{"$value": "...", "secret": true}`,
+/// instead of a value that is passed on as JSON.
+pub const VALUE_KEY: &str = "$value";
+
+/// What a definition says about a variable besides its value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Meta {
+    pub description: Option<String>,
+    pub required: bool,
+    pub secret: bool,
+    /// A JSON schema type name, like `integer`, that the value has to be readable as.
+    pub value_type: Option<String>,
+    /// Used if no config file sets a value.
+    pub default: Option<String>,
+    /// Where the metadata was last declared.
+    pub source: Option<Source>,
+}
+
+/// Key of a config file's root object that names a JSON schema for the variables. It is not a variable itself.
+pub const SCHEMA_KEY: &str = "$schema";
+
+/// Key of a config file's root object that lists the public keys its values are encrypted to.
+pub const RECIPIENTS_KEY: &str = "$recipients";
+
+/// Key of a config file's root object that lists the variables that are kept from the environment with
+/// `--clean-env`.
+pub const KEEP_KEY: &str = "$keep";
+
+/// Key of a config file's root object that, if `false`, lets variables from the environment win over the
+/// file's variables.
+pub const OVERRIDE_KEY: &str = "$override";
+
+/// Whether `key` has a meaning for json_env instead of being a variable.
+pub fn is_reserved(key: &str) -> bool {
+    [SCHEMA_KEY, RECIPIENTS_KEY, KEEP_KEY, OVERRIDE_KEY].contains(&key)
+}
+
+/// The merged result of all config files.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub vars: Vars,
+    /// The schema named by the last config file with a `"$schema"` key.
+    pub schema: Option<String>,
+    /// Metadata from definitions, merged over all config files. Keys may be declared without a value.
+    pub meta: BTreeMap<String, Meta>,
+    /// The variables listed in `"$keep"` by any config file.
+    pub keep: Vec<String>,
+}
+
+impl Config {
+    /// Whether the value of `key` must not be shown, because it is flagged or looks like a secret.
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.meta.get(key).is_some_and(|m| m.secret) || secret::looks_secret(key)
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// The variables and their metadata from the root object of `contents`, as if it was the only config file.
+    pub fn test(file_name: &str, contents: &str) -> Config {
+        let layers = parse_layers(file_name, contents, "$", &Options::default()).unwrap();
+        let mut config = Config::default();
+        add_values_to_map(&layers, false, &mut config.vars);
+        collect_meta(&layers, &mut config.meta);
+        config
+    }
+}
+
+/// An object selected from a config file by a JSON path.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub file: String,
+    pub json_path: String,
+    pub value: Value,
+    /// The line on which each key of `value` is defined.
+    pub lines: HashMap<String, usize>,
+    /// The `"$schema"` of the file, relative paths are resolved against the file's directory.
+    pub schema: Option<String>,
+    /// The variables listed in the file's `"$keep"`.
+    pub keep: Vec<String>,
+    /// Whether the file's variables replace those in the environment, `false` if it says `"$override": false`.
+    pub overrides: bool,
+}
+
+/// Whether the commands for `keys` in `file` may run, `silent` is the `-s` flag.
+pub type AllowCommands = fn(file: &str, keys: &[String], silent: bool) -> bool;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    pub expand: bool,
+    pub lenient: bool,
+    pub silent: bool,
+    /// Fail instead of warning when a file with secrets can be read by other users or is tracked by git.
+    pub strict: bool,
+    /// Let variables from the environment win over those from all config files.
+    pub no_override: bool,
+    /// Decrypt encrypted values. Without it, they are left as they are.
+    pub decrypt: bool,
+    /// Decides whether the `$cmd` objects and named references in a file may run. Without it, they are left as
+    /// they are.
+    pub allow_commands: Option<AllowCommands>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// A problem with a file or path given on the command line, reported together with the usage.
+    Argument(String),
+    /// An error that has a complete message of its own, e.g. a diagnostic.
+    Report(String),
+}
+
+impl std::error::Error for LoadError {}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Argument(message) | LoadError::Report(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+/// Read all config files, apply the JSON path with the same index to each of them and merge the results.
+/// Files without a JSON path use `$`.
+pub fn load(files: &[String], paths: &[String], options: &Options) -> Result<Config, LoadError> {
+    // Check the JSON paths before reading any file, so that their errors are not mixed up with JSON errors
+    for path in paths {
+        if let Err(e) = JsonPathInst::from_str(path) {
+            return Err(LoadError::Report(
+                Diagnostic::from_json_path_error(&e, path).to_string(),
+            ));
+        }
+    }
+    let mut config = Config::default();
+    let mut keys = crypt::Keys::default();
+    let mut resolvers =
+        Resolvers::from_config().map_err(|e| LoadError::Report(format!("{e:#}")))?;
+    let mut not_overriding = Vec::new();
+    for (i, file_name) in files.iter().enumerate() {
+        let json_path = paths.get(i).map(String::as_str).unwrap_or("$");
+        let mut layers = read_layers(file_name, json_path, options)?;
+        // Values that are left encrypted or unresolved are secrets all the same
+        let decrypted = match options.decrypt {
+            true => crypt::decrypt_layers(&mut layers, &mut keys)
+                .map_err(|e| LoadError::Report(format!("{e:#}")))?,
+            false => crypt::encrypted_keys(&layers),
+        };
+        let references = resolvers.references(&layers);
+        let resolved = match options.allow_commands {
+            Some(allow_commands) => {
+                if !references.is_empty() && !allow_commands(file_name, &references, options.silent)
+                {
+                    return Err(LoadError::Report(format!(
+                        "Refused to run the commands for {} in '{file_name}', it is not whitelisted or trusted",
+                        references.join(", ")
+                    )));
+                }
+                resolvers
+                    .resolve_layers(&mut layers)
+                    .map_err(|e| LoadError::Report(format!("{e:#}")))?
+            }
+            None => references,
+        };
+        let protected: Vec<String> = decrypted.into_iter().chain(resolved).collect();
+        let problems = audit::problems(file_name, &audit::plain_secrets(&layers, &protected));
+        if options.strict && !problems.is_empty() {
+            return Err(LoadError::Report(problems.join("\n")));
+        }
+        if !options.silent {
+            for problem in problems {
+                eprintln!("Warning: {problem}");
+            }
+        }
+        for key in protected {
+            config.meta.entry(key).or_default().secret = true;
+        }
+        if let Some(schema) = layers.iter().find_map(|layer| layer.schema.clone()) {
+            config.schema = Some(schema);
+        }
+        for key in layers
+            .first()
+            .map(|layer| &layer.keep)
+            .into_iter()
+            .flatten()
+        {
+            if !config.keep.contains(key) {
+                config.keep.push(key.clone());
+            }
+        }
+        if options.no_override || layers.iter().any(|layer| !layer.overrides) {
+            not_overriding.push(file_name.clone());
+        }
+        add_values_to_map(&layers, options.expand, &mut config.vars);
+        collect_meta(&layers, &mut config.meta);
+    }
+    keep_environment(&mut config.vars, &not_overriding);
+    Ok(config)
+}
+
+/// Replace the variables that were last set by one of `files` with their value in the environment, if they
+/// have one there. The replaced definitions are kept as overridden.
+fn keep_environment(vars: &mut Vars, files: &[String]) {
+    for (key, var) in vars.iter_mut() {
+        if !files.contains(&var.source.file) {
+            continue;
+        }
+        let Ok(value) = env::var(key) else {
+            continue;
+        };
+        let environment = Var {
+            value,
+            source: Source {
+                file: ENVIRONMENT.to_string(),
+                json_path: "$".to_string(),
+                line: None,
+            },
+            unexpanded: None,
+            overridden: Vec::new(),
+            change: Change::Set,
+        };
+        let previous = std::mem::replace(var, environment);
+        var.overridden = previous.overridden;
+        var.overridden.push(Overridden {
+            value: previous.value,
+            source: previous.source,
+        });
+    }
+}
+
+/// Read a config file and select the objects that `json_path` points to.
+pub fn read_layers(
+    file_name: &str,
+    json_path: &str,
+    options: &Options,
+) -> Result<Vec<Layer>, LoadError> {
+    let Ok(mut file) = File::open(file_name) else {
+        return Err(LoadError::Argument(format!("Could not open '{file_name}'")));
+    };
+    let mut contents = String::new();
+    if file.read_to_string(&mut contents).is_err() {
+        return Err(LoadError::Argument(format!(
+            "Could not read JSON in '{file_name}'"
+        )));
+    }
+    parse_layers(file_name, &contents, json_path, options)
+}
+
+/// Parse the contents of a config file and select the objects that `json_path` points to.
+pub fn parse_layers(
+    file_name: &str,
+    contents: &str,
+    json_path: &str,
+    options: &Options,
+) -> Result<Vec<Layer>, LoadError> {
+    let document = json::parse(contents).map_err(|e| {
+        LoadError::Report(Diagnostic::from_json_error(&e, file_name, contents).to_string())
+    })?;
+    check_duplicate_keys(file_name, contents, &document, options)?;
+    let schema = schema_reference(&document, file_name);
+    let keep = keep_list(&document, file_name)?;
+    let overrides = override_setting(&document, file_name)?;
+    let layers = select(&document, contents, json_path)
+        .map_err(|e| {
+            LoadError::Report(format!(
+                "Error while applying JSON path '{json_path}': {e} in '{file_name}'"
+            ))
+        })?
+        .into_iter()
+        .map(|(value, lines)| Layer {
+            file: file_name.to_string(),
+            json_path: json_path.to_string(),
+            value,
+            lines,
+            schema: schema.clone(),
+            keep: keep.clone(),
+            overrides,
+        })
+        .collect::<Vec<_>>();
+    if layers.is_empty() {
+        return Err(LoadError::Argument(format!(
+            "There is nothing in file '{file_name}' at path '{json_path}'"
+        )));
+    }
+    Ok(layers)
+}
+
+fn schema_reference(document: &json::Node, file_name: &str) -> Option<String> {
+    let json::Kind::Object(members) = &document.kind else {
+        return None;
+    };
+    let member = members.iter().rev().find(|m| m.key == SCHEMA_KEY)?;
+    let json::Kind::String(reference) = &member.value.kind else {
+        return None;
+    };
+    if reference.contains("://") {
+        return Some(reference.clone());
+    }
+    let directory = Path::new(file_name).parent().unwrap_or(Path::new(""));
+    Some(directory.join(reference).to_string_lossy().to_string())
+}
+
+fn keep_list(document: &json::Node, file_name: &str) -> Result<Vec<String>, LoadError> {
+    let Some(value) = root_value(document, KEEP_KEY) else {
+        return Ok(Vec::new());
+    };
+    let names: Option<Vec<String>> = value.as_array().and_then(|items| {
+        items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect()
+    });
+    names.ok_or_else(|| {
+        LoadError::Report(format!(
+            "\"{KEEP_KEY}\" in '{file_name}' must be a list of variable names"
+        ))
+    })
+}
+
+fn override_setting(document: &json::Node, file_name: &str) -> Result<bool, LoadError> {
+    match root_value(document, OVERRIDE_KEY) {
+        None => Ok(true),
+        Some(Value::Bool(overrides)) => Ok(overrides),
+        Some(_) => Err(LoadError::Report(format!(
+            "\"{OVERRIDE_KEY}\" in '{file_name}' must be true or false"
+        ))),
+    }
+}
+
+/// The value of `key` in the root object of `document`, if it has one.
+fn root_value(document: &json::Node, key: &str) -> Option<Value> {
+    let json::Kind::Object(members) = &document.kind else {
+        return None;
+    };
+    let member = members.iter().rev().find(|m| m.key == key)?;
+    Some(member.value.to_value())
+}
+
+/// Apply a JSON path to a parsed document. Returns the selected values and the line of each of their keys.
+fn select(
+    document: &json::Node,
+    source: &str,
+    path: &str,
+) -> anyhow::Result<Vec<(Value, HashMap<String, usize>)>> {
+    let path = JsonPathInst::from_str(path).map_err(anyhow::Error::msg)?;
+    let json = Box::new(document.to_value());
+    // The JSON path finder hands out references into `json`, whose heap allocations do not move
+    // when the box is moved into the finder. Their addresses identify the node they were built from.
+    let mut nodes = HashMap::new();
+    index_nodes(&json, document, &mut nodes);
+    let finder = JsonPathFinder::new(json, Box::new(path));
+    let selected = finder
+        .find_slice()
+        .into_iter()
+        .map(|found| match found {
+            JsonPathValue::Slice(value) => {
+                let lines = match nodes.get(&(value as *const Value)).map(|node| &node.kind) {
+                    Some(json::Kind::Object(members)) => members
+                        .iter()
+                        .map(|m| (m.key.clone(), json::line_col(source, m.key_span.start).0))
+                        .collect(),
+                    _ => HashMap::new(),
+                };
+                (value.clone(), lines)
+            }
+            JsonPathValue::NewValue(value) => (value, HashMap::new()),
+        })
+        .collect();
+    Ok(selected)
+}
+
+fn index_nodes<'a>(
+    value: &Value,
+    node: &'a json::Node,
+    nodes: &mut HashMap<*const Value, &'a json::Node>,
+) {
+    nodes.insert(value as *const Value, node);
+    match (value, &node.kind) {
+        (Value::Object(map), json::Kind::Object(members)) => {
+            for (key, child) in map {
+                // Like serde_json, the last of several identical keys wins
+                if let Some(member) = members.iter().rev().find(|m| &m.key == key) {
+                    index_nodes(child, &member.value, nodes);
+                }
+            }
+        }
+        (Value::Array(values), json::Kind::Array(items)) => {
+            for (child, item) in values.iter().zip(items) {
+                index_nodes(child, item, nodes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Report keys that are defined twice in the same object. Exact duplicates are errors unless
+/// `--lenient` is given, keys that only differ in case are always warnings.
+fn check_duplicate_keys(
+    file_name: &str,
+    contents: &str,
+    document: &json::Node,
+    options: &Options,
+) -> Result<(), LoadError> {
+    let mut errors = Vec::new();
+    for dup in document.duplicate_keys() {
+        let (first_line, _) = json::line_col(contents, dup.first_span.start);
+        let (level, message, help) = if dup.case_only {
+            (
+                Level::Warning,
+                format!("keys '{}' and '{}' only differ in case", dup.first, dup.second),
+                format!(
+                    "'{}' is defined on line {first_line}, environment variables are case-insensitive on some platforms",
+                    dup.first
+                ),
+            )
+        } else if options.lenient {
+            (
+                Level::Warning,
+                format!("duplicate key '{}'", dup.second),
+                format!("the value defined on line {first_line} is ignored"),
+            )
+        } else {
+            (
+                Level::Error,
+                format!("duplicate key '{}'", dup.second),
+                format!("first defined on line {first_line}, use --lenient to only warn"),
+            )
+        };
+        let mut diagnostic =
+            Diagnostic::at(level, &message, file_name, contents, dup.second_span.start);
+        diagnostic.help = Some(help);
+        if level == Level::Error {
+            errors.push(diagnostic.to_string());
+        } else if !options.silent {
+            eprintln!("{diagnostic}");
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(LoadError::Report(errors.join("\n")))
+    }
+}
+
+/// Add the members of the objects in `layers` to `vars`, replacing earlier definitions.
+pub fn add_values_to_map(layers: &[Layer], should_expand: bool, vars: &mut Vars) {
+    for layer in layers {
+        let Some(in_val) = layer.value.as_object() else {
+            continue;
+        };
+        for (key, val) in in_val {
+            if is_reserved(key) {
+                continue;
+            }
+            let source = Source {
+                file: layer.file.clone(),
+                json_path: layer.json_path.clone(),
+                line: layer.lines.get(key).copied(),
+            };
+            if let Some(change) = Change::from_value(val) {
+                let previous = vars.get(key).map(|var| (&var.change, var.value.as_str()));
+                let (change, value) = change.merge(key, previous);
+                let var = Var {
+                    value,
+                    source,
+                    unexpanded: None,
+                    overridden: Vec::new(),
+                    change,
+                };
+                insert(vars, key, var);
+                continue;
+            }
+            let val = match definition(val) {
+                Some(definition) => match definition.get(VALUE_KEY) {
+                    Some(value) if !value.is_null() => value,
+                    // A default does not replace a value from an earlier file
+                    _ => match definition.get("default") {
+                        Some(default) if !vars.contains_key(key) => default,
+                        _ => continue,
+                    },
+                },
+                None => val,
+            };
+            let val_str = to_env_string(val);
+            let mut var = Var {
+                value: val_str.clone(),
+                source,
+                unexpanded: None,
+                overridden: Vec::new(),
+                change: Change::Set,
+            };
+            if should_expand {
+                let expanded_val = interpolate(&val_str, |name| env::var(name).ok());
+                if expanded_val != val_str {
+                    var.value = expanded_val;
+                    var.unexpanded = Some(val_str);
+                }
+            }
+            insert(vars, key, var);
+        }
+    }
+}
+
+/// Insert `var`, and remember the definition it replaces.
+fn insert(vars: &mut Vars, key: &str, mut var: Var) {
+    if let Some(previous) = vars.remove(key) {
+        var.overridden = previous.overridden;
+        var.overridden.push(Overridden {
+            value: previous.value,
+            source: previous.source,
+        });
+    }
+    vars.insert(key.to_string(), var);
+}
+
+/// The string form of a JSON value in the environment.
+pub fn to_env_string(value: &Value) -> String {
+    match value {
+        // the as_str is needed, because we get quotes otherwise
+        Value::String(s) => s.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// If `value` is the definition of a variable, i.e. an object with a `$value` key, return it.
+pub fn definition(value: &Value) -> Option<&serde_json::Map<String, Value>> {
+    value
+        .as_object()
+        .filter(|object| object.contains_key(VALUE_KEY))
+}
+
+/// The values of the members of a layer, or their `$value` if they are definitions.
+pub fn values_mut(layer: &mut Layer) -> impl Iterator<Item = (&String, &mut Value)> {
+    layer
+        .value
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|object| object.iter_mut())
+        .map(|(key, value)| {
+            if definition(value).is_some() {
+                (key, &mut value[VALUE_KEY])
+            } else {
+                (key, value)
+            }
+        })
+}
+
+/// Merge the metadata of all definitions in `layers` into `meta`. Later files override single fields.
+pub fn collect_meta(layers: &[Layer], meta: &mut BTreeMap<String, Meta>) {
+    for layer in layers {
+        let Some(object) = layer.value.as_object() else {
+            continue;
+        };
+        for (key, value) in object {
+            let Some(definition) = definition(value) else {
+                continue;
+            };
+            let entry = meta.entry(key.clone()).or_default();
+            if let Some(description) = definition.get("description").and_then(Value::as_str) {
+                entry.description = Some(description.to_string());
+            }
+            if let Some(required) = definition.get("required").and_then(Value::as_bool) {
+                entry.required = required;
+            }
+            if let Some(secret) = definition.get("secret").and_then(Value::as_bool) {
+                entry.secret = secret;
+            }
+            if let Some(value_type) = definition.get("type").and_then(Value::as_str) {
+                entry.value_type = Some(value_type.to_string());
+            }
+            if let Some(default) = definition.get("default") {
+                entry.default = Some(to_env_string(default));
+            }
+            entry.source = Some(Source {
+                file: layer.file.clone(),
+                json_path: layer.json_path.clone(),
+                line: layer.lines.get(key).copied(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_lines_and_overrides() {
+        let dir = env::temp_dir().join(format!("json_env_loader_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let defaults = dir.join("defaults.json");
+        let local = dir.join("local.json");
+        std::fs::write(
+            &defaults,
+            "{\n  \"USER\": \"TO_BE_OVERWRITTEN\",\n  \"URL\": \"x\"\n}",
+        )
+        .unwrap();
+        std::fs::write(&local, "{\"Values\": {\n\n  \"USER\": \"admin\"}}").unwrap();
+        let files = [
+            defaults.to_str().unwrap().to_string(),
+            local.to_str().unwrap().to_string(),
+        ];
+        let vars = load(
+            &files,
+            &["$".to_string(), "$.Values".to_string()],
+            &Options::default(),
+        )
+        .unwrap()
+        .vars;
+        let user = &vars["USER"];
+        assert_eq!(user.value, "admin");
+        assert_eq!(user.source.line, Some(3));
+        assert_eq!(user.source.json_path, "$.Values");
+        assert_eq!(user.overridden.len(), 1);
+        assert_eq!(user.overridden[0].value, "TO_BE_OVERWRITTEN");
+        assert_eq!(user.overridden[0].source.line, Some(2));
+        assert_eq!(vars["URL"].source.line, Some(3));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_definitions() {
+        let defaults = r#"{
+            "DB_PASSWORD": {"$value": "hunter2", "secret": true, "description": "Database password"},
+            "PORT": {"$value": null, "default": 8080, "type": "integer"},
+            "TOKEN": {"$value": null, "required": true},
+            "LOGGER": {"type": "console"}
+        }"#;
+        let config = Config::test("defaults.json", defaults);
+        assert_eq!(config.vars["DB_PASSWORD"].value, "hunter2");
+        assert_eq!(config.vars["PORT"].value, "8080");
+        assert_eq!(config.vars["LOGGER"].value, r#"{"type":"console"}"#);
+        assert!(!config.vars.contains_key("TOKEN"));
+        assert!(config.meta["TOKEN"].required);
+        assert!(config.is_secret("DB_PASSWORD"));
+        assert_eq!(config.meta["PORT"].value_type.as_deref(), Some("integer"));
+
+        // A default does not replace an earlier value
+        let mut vars = Config::test("local.json", r#"{"PORT": 3000}"#).vars;
+        add_values_to_map(
+            &parse_layers("defaults.json", defaults, "$", &Options::default()).unwrap(),
+            false,
+            &mut vars,
+        );
+        assert_eq!(vars["PORT"].value, "3000");
+    }
+
+    #[test]
+    fn reads_keep_list() {
+        let contents = r#"{"$keep": ["HOME", "TERM"], "prod": {"PORT": 80}}"#;
+        let layers = parse_layers("a.json", contents, "$.prod", &Options::default()).unwrap();
+        assert_eq!(layers[0].keep, vec!["HOME", "TERM"]);
+        assert!(!Config::test("a.json", contents).vars.contains_key(KEEP_KEY));
+
+        let error = parse_layers("a.json", r#"{"$keep": "HOME"}"#, "$", &Options::default());
+        assert!(matches!(error, Err(LoadError::Report(_))));
+    }
+
+    #[test]
+    fn environment_wins_over_files_that_do_not_override() {
+        env::set_var("JSON_ENV_LOADER_TEST_PORT", "3000");
+        let dir = env::temp_dir().join(format!("json_env_override_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let defaults = dir.join("defaults.json");
+        let local = dir.join("local.json");
+        std::fs::write(
+            &defaults,
+            r#"{"$override": false, "JSON_ENV_LOADER_TEST_PORT": "8080"}"#,
+        )
+        .unwrap();
+        std::fs::write(&local, r#"{"JSON_ENV_LOADER_TEST_PORT": "9000"}"#).unwrap();
+        let load_files = |files: &[&std::path::PathBuf], options: &Options| {
+            let files: Vec<String> = files
+                .iter()
+                .map(|file| file.to_str().unwrap().to_string())
+                .collect();
+            load(&files, &[], options).unwrap().vars["JSON_ENV_LOADER_TEST_PORT"].clone()
+        };
+        let var = load_files(&[&defaults], &Options::default());
+        assert_eq!(var.value, "3000");
+        assert_eq!(var.source.file, ENVIRONMENT);
+        assert_eq!(var.overridden[0].value, "8080");
+        // A later file that overrides wins again
+        let var = load_files(&[&defaults, &local], &Options::default());
+        assert_eq!(var.value, "9000");
+        let options = Options {
+            no_override: true,
+            ..Options::default()
+        };
+        assert_eq!(load_files(&[&defaults, &local], &options).value, "3000");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
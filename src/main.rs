@@ -1,20 +1,51 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
 use std::fmt::{Display, Formatter};
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{self, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::string::ToString;
-use std::{env, fs, process};
+use std::{env, fs, process, thread};
 
-use anyhow::{Error, Result};
 use clap::error::ErrorKind;
 use clap::CommandFactory;
 use clap::Parser;
 use dialoguer::Confirm;
 use dirs::home_dir;
-use jsonpath_rust::JsonPathFinder;
-use serde_json::Value;
+
+use crate::change::Change;
+use crate::diagnostic::Diagnostic;
+use crate::loader::{Config, Layer, LoadError, Options, Vars};
+use crate::policy::Policy;
+use crate::secret_files::SecretFiles;
+
+mod audit;
+mod change;
+mod config_dir;
+mod crypt;
+mod diagnostic;
+mod diff;
+mod docs;
+mod edit;
+mod example;
+mod explain;
+mod format;
+mod git;
+mod interpolate;
+mod json;
+mod list;
+mod loader;
+mod mask;
+mod patch;
+mod policy;
+mod prompt;
+mod resolve;
+mod scan;
+mod schema;
+mod secret;
+mod secret_files;
+mod shell;
+mod table;
 
 struct Shell {
     shell_type: ShellType,
@@ -23,7 +54,7 @@ struct Shell {
     include_command: &'static str,
 }
 
-#[derive(Debug, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
 enum ShellType {
     Bash,
     Zsh,
@@ -40,18 +71,6 @@ impl Display for ShellType {
     }
 }
 
-impl Clone for ShellType {
-    fn clone(&self) -> Self {
-        match self {
-            ShellType::Bash => ShellType::Bash,
-            ShellType::Zsh => ShellType::Zsh,
-            ShellType::Fish => ShellType::Fish,
-        }
-    }
-}
-
-impl Copy for ShellType {}
-
 impl Clone for Shell {
     fn clone(&self) -> Self {
         Shell {
@@ -103,6 +122,27 @@ struct Args {
     /// Do not run an application but print export commands. (can be sourced)
     #[arg(long, default_value_t = false)]
     export: bool,
+    /// Add a comment to each export command that explains where its value came from
+    #[arg(long, default_value_t = false, requires = "export")]
+    why: bool,
+    /// Pass secrets as files in a private directory instead of as variables: DB_PASSWORD becomes DB_PASSWORD_FILE with the path of the file
+    #[arg(long, default_value_t = false, conflicts_with = "export")]
+    secrets_as_files: bool,
+    /// Replace the values of secrets in the output of the executable with '****'. In GitHub Actions, also tell it to mask them in the log.
+    #[arg(long, default_value_t = false, conflicts_with = "export")]
+    mask_output: bool,
+    /// Replace $VAR and ${VAR} in the arguments of the executable with the values of the variables
+    #[arg(long, default_value_t = false, conflicts_with = "export")]
+    expand_args: bool,
+    /// Let variables that are already set in the environment win over those from the config files
+    #[arg(long, default_value_t = false)]
+    no_override: bool,
+    /// Start the executable with only the variables from the config files, and those listed with --keep or in "$keep"
+    #[arg(long, default_value_t = false, conflicts_with = "export")]
+    clean_env: bool,
+    /// Variables from the environment that are kept with --clean-env, e.g. PATH,HOME,TERM
+    #[arg(long, value_delimiter = ',', requires = "clean_env")]
+    keep: Vec<String>,
     /// The JSON files from which the environment variables are taken from
     #[arg(short, long)]
     config_files: Vec<String>,
@@ -114,6 +154,15 @@ struct Args {
     /// add a script to your shell configuration that automatically exports variables defined in .env.json when changing into a directory that contains such a file.
     #[arg(long, default_value_t = false)]
     install: bool,
+    /// A JSON schema that the variables are validated against before they are used. Overrides "$schema" in the config files.
+    #[arg(long)]
+    schema: Option<String>,
+    /// Only warn about duplicate keys in config files instead of failing
+    #[arg(long, default_value_t = false)]
+    lenient: bool,
+    /// Fail instead of warning when a config file with secrets in it can be read by other users or is tracked by git
+    #[arg(long, default_value_t = false)]
+    strict: bool,
     /// Silent mode, do not report errors (useful for scripts). Implies 'yes' to all questions.
     #[arg(short, long, default_value_t = false)]
     silent: bool,
@@ -129,12 +178,202 @@ struct Args {
     /// Print the init script for the supplied shell
     #[arg(long)]
     init: Option<ShellType>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands. Options that select config files, like `-c` and `-p`, go before the subcommand.
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Show where the value of each variable came from and which values it replaced
+    Explain {
+        /// The variable to explain, all variables if omitted
+        key: Option<String>,
+        /// The output format
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+        /// Show the values of secrets
+        #[arg(long, default_value_t = false)]
+        reveal: bool,
+    },
+    /// List the variables and the files they come from
+    List {
+        /// The output format
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+        /// Show the values of secrets
+        #[arg(long, default_value_t = false)]
+        reveal: bool,
+    },
+    /// Compare the variables with other config files, or with the current environment
+    Diff {
+        /// The config files to compare with. Without any, the current environment is used.
+        #[arg(short, long)]
+        config_files: Vec<String>,
+        /// JSON paths into the config files to compare with, in order
+        #[arg(short, long, default_value = "$")]
+        paths: Vec<String>,
+        /// Also load .env.<PROFILE>.json from the directory of the loaded config file, after it
+        #[arg(long)]
+        profile: Option<String>,
+        /// Compare with .env.<AGAINST_PROFILE>.json, loaded after the config files to compare with, or after the
+        /// loaded ones if there are none
+        #[arg(long)]
+        against_profile: Option<String>,
+        /// Show the values of secrets
+        #[arg(long, default_value_t = false)]
+        reveal: bool,
+    },
+    /// Check that an example config file has the same keys as the real one
+    CheckExample {
+        /// The example file, `.env.example.json` next to the first config file if omitted
+        #[arg(long)]
+        example: Option<String>,
+    },
+    /// Print an example config file with the same keys as the real one, but without its values
+    MakeExample {
+        /// Use placeholders of the same type as the values, like `"<string>"` or `0`, instead of empty strings
+        #[arg(long, default_value_t = false)]
+        typed: bool,
+        /// Write the example to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Print documentation of all variables: description, default, whether they are required or secret
+    Docs {
+        /// The output format
+        #[arg(long, value_enum, default_value_t = DocsFormat::Markdown)]
+        format: DocsFormat,
+    },
+    /// Format config files canonically: the same indentation everywhere, one key per line, normalized escapes
+    Fmt {
+        /// The files to format. Without any, every config file that would be loaded.
+        files: Vec<String>,
+        /// Only check whether the files are formatted and exit with status 1 if not
+        #[arg(long, default_value_t = false)]
+        check: bool,
+        /// Sort the keys of all objects
+        #[arg(long, default_value_t = false)]
+        sort_keys: bool,
+        /// The number of spaces to indent each level with
+        #[arg(long, default_value_t = 2)]
+        indent: usize,
+        /// Format the whitelist in ~/.config/json_env instead of the config files
+        #[arg(long, default_value_t = false)]
+        whitelist: bool,
+    },
+    /// Start your shell with the variables set. JSON_ENV_ACTIVE is set to the config file.
+    Shell {
+        /// Also load .env.<PROFILE>.json from the directory of the config file, after it
+        #[arg(long)]
+        profile: Option<String>,
+        /// Put "(json_env)" in front of the prompt of bash, zsh, fish or cmd
+        #[arg(long, default_value_t = false)]
+        prompt: bool,
+        /// Start the shell even if this is already a json_env shell
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Search for secrets in plain text in config files, exit with status 1 if any are found
+    Scan {
+        /// Files to scan and directories to search for .env*.json files, the current directory if omitted
+        paths: Vec<String>,
+        /// Keys whose values are not secrets, e.g. test fixtures
+        #[arg(long = "allow", value_delimiter = ',')]
+        allowed_keys: Vec<String>,
+        /// Files that are not scanned, matched against the end of their path
+        #[arg(long = "allow-file")]
+        allowed_files: Vec<String>,
+    },
+    /// Encrypt variables in a config file, so that only the recipients listed in the file can read them
+    Encrypt {
+        /// The names of the variables
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// The config file to change, the first config file if omitted
+        #[arg(short, long)]
+        file: Option<String>,
+        /// A JSON path to the object that contains the variables, the first path given with -p if omitted
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Replace encrypted variables in a config file with their plain values
+    Decrypt {
+        /// The names of the variables
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// The config file to change, the first config file if omitted
+        #[arg(short, long)]
+        file: Option<String>,
+        /// A JSON path to the object that contains the variables, the first path given with -p if omitted
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Create a private key for decrypting values, save it in ~/.config/json_env/keys and print its public key
+    Keygen,
+    /// Edit a config file in $EDITOR with its encrypted values decrypted, and encrypt them again afterwards
+    Edit {
+        /// The config file to edit, the first config file if omitted
+        file: Option<String>,
+    },
+    /// Print the value of a variable
+    Get {
+        /// The name of the variable
+        key: String,
+    },
+    /// Set a variable in a config file, keeping the formatting of the file
+    Set {
+        /// The name of the variable
+        key: String,
+        /// The value, as a string
+        #[arg(required_unless_present = "json", conflicts_with = "json")]
+        value: Option<String>,
+        /// The value as JSON, e.g. '{"a": 1}'
+        #[arg(long)]
+        json: Option<String>,
+        /// The config file to change, the first config file if omitted
+        #[arg(short, long)]
+        file: Option<String>,
+        /// A JSON path to the object that contains the variable, the first path given with -p if omitted
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Remove a variable from a config file, keeping the formatting of the file
+    Unset {
+        /// The name of the variable
+        key: String,
+        /// The config file to change, the first config file if omitted
+        #[arg(short, long)]
+        file: Option<String>,
+        /// A JSON path to the object that contains the variable, the first path given with -p if omitted
+        #[arg(long)]
+        path: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum DocsFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ListFormat {
+    Table,
+    Json,
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Table,
+    Json,
 }
 
 /// `json_env` is [dotenv](https://github.com/motdotla/dotenv), but with JSON.
 /// See the [readme](Readme.md) for more information.
 fn main() {
-    let mut args: Args = Args::parse();
+    let args: Args = Args::try_parse().unwrap_or_else(|e| exit_with_parse_error(e));
     let mut cmd = Args::command();
 
     if args.install {
@@ -182,7 +421,6 @@ fn main() {
         }
     }
 
-    let mut env_vars: HashMap<String, String> = HashMap::new();
     if args.print_config_path {
         if let Some(config_path) = find_env_file() {
             println!("{}", config_path.to_str().unwrap());
@@ -193,6 +431,306 @@ fn main() {
         }
     }
 
+    if let Some(command) = &args.command {
+        match command {
+            Command::Explain {
+                key,
+                format,
+                reveal,
+            } => {
+                let config = load_vars(&args, &mut cmd);
+                if let Some(key) = key {
+                    if !config.vars.contains_key(key) {
+                        if !args.silent {
+                            eprintln!("'{key}' is not defined in any config file");
+                        }
+                        process::exit(1);
+                    }
+                }
+                let selected: Vars = shown_vars(&config, *reveal)
+                    .into_iter()
+                    .filter(|(k, _)| key.as_ref().is_none_or(|key| key == k))
+                    .collect();
+                match format {
+                    Format::Table => print!("{}", explain::table(selected.iter())),
+                    Format::Json => println!("{:#}", explain::json(selected.iter())),
+                }
+            }
+            Command::List { format, reveal } => {
+                let config = load_vars(&args, &mut cmd);
+                let shown = shown_vars(&config, *reveal);
+                match format {
+                    ListFormat::Table => print!("{}", list::table(shown.iter())),
+                    ListFormat::Json => println!("{:#}", list::json(shown.iter())),
+                    ListFormat::Plain => print!("{}", list::plain(shown.iter())),
+                }
+            }
+            Command::Diff {
+                config_files: against_files,
+                paths: against_paths,
+                profile,
+                against_profile,
+                reveal,
+            } => {
+                let (old_files, old_paths) = with_profile(
+                    config_files(&args, &mut cmd),
+                    &args.paths,
+                    profile,
+                    &args,
+                    &mut cmd,
+                );
+                let old_config = load_config(old_files, &old_paths, &args, &mut cmd);
+                let old = values(&old_config.vars);
+                let (new, new_config) = if against_files.is_empty() && against_profile.is_none() {
+                    // Only the keys from the config matter when looking for drift in the environment
+                    let new = env::vars().filter(|(k, _)| old.contains_key(k)).collect();
+                    (new, Config::default())
+                } else {
+                    let (new_files, new_paths) = if against_files.is_empty() {
+                        (config_files(&args, &mut cmd), &args.paths)
+                    } else {
+                        (against_files.clone(), against_paths)
+                    };
+                    let (new_files, new_paths) =
+                        with_profile(new_files, new_paths, against_profile, &args, &mut cmd);
+                    let new_config = load_config(new_files, &new_paths, &args, &mut cmd);
+                    (values(&new_config.vars), new_config)
+                };
+                let differences = diff::diff(&old, &new);
+                for difference in &differences {
+                    let key = difference.key();
+                    if !reveal && (old_config.is_secret(key) || new_config.is_secret(key)) {
+                        println!("{}", difference.clone().masked());
+                    } else {
+                        println!("{difference}");
+                    }
+                }
+                if !differences.is_empty() {
+                    process::exit(1);
+                }
+            }
+            Command::CheckExample { example } => {
+                let mut config_files = config_files(&args, &mut cmd);
+                // Values in the local file are not shared, so they do not need to be in the example
+                if config_files.len() > 1 {
+                    config_files.retain(|file| {
+                        Path::new(file).file_name() != Some(OsStr::new(prompt::LOCAL_FILE))
+                    });
+                }
+                let example = example.clone().unwrap_or_else(|| {
+                    let directory = Path::new(&config_files[0])
+                        .parent()
+                        .unwrap_or(Path::new(""));
+                    directory
+                        .join(".env.example.json")
+                        .to_string_lossy()
+                        .to_string()
+                });
+                let actual = example::key_paths(&read_all_layers(
+                    &config_files,
+                    &args.paths,
+                    &args,
+                    &mut cmd,
+                ));
+                let expected = example::key_paths(&read_all_layers(
+                    std::slice::from_ref(&example),
+                    &args.paths,
+                    &args,
+                    &mut cmd,
+                ));
+                let missing: Vec<&String> = actual.difference(&expected).collect();
+                let extra: Vec<&String> = expected.difference(&actual).collect();
+                if missing.is_empty() && extra.is_empty() {
+                    return;
+                }
+                if !args.silent {
+                    for key in missing {
+                        println!("Missing in '{example}': {key}");
+                    }
+                    for key in extra {
+                        println!("Not in the config: {key}");
+                    }
+                }
+                process::exit(1);
+            }
+            Command::MakeExample { typed, output } => {
+                let config_file = &config_files(&args, &mut cmd)[0];
+                let contents = match fs::read_to_string(config_file) {
+                    Ok(contents) => contents,
+                    Err(e) => exit_with_load_error(
+                        LoadError::Argument(format!("Could not read '{config_file}': {e}")),
+                        &args,
+                        &mut cmd,
+                    ),
+                };
+                let document = match json::parse(&contents) {
+                    Ok(document) => document,
+                    Err(e) => exit_with_load_error(
+                        LoadError::Report(
+                            Diagnostic::from_json_error(&e, config_file, &contents).to_string(),
+                        ),
+                        &args,
+                        &mut cmd,
+                    ),
+                };
+                let example = json::to_pretty_string(&example::redact(&document, *typed), "  ");
+                match output {
+                    Some(output) => {
+                        if let Err(e) = fs::write(output, format!("{example}\n")) {
+                            if !args.silent {
+                                eprintln!("Could not write '{output}': {e}");
+                            }
+                            process::exit(1);
+                        }
+                    }
+                    None => println!("{example}"),
+                }
+            }
+            Command::Docs { format } => {
+                // The documentation does not show values, so they are neither decrypted nor resolved
+                let options = Options {
+                    decrypt: false,
+                    allow_commands: None,
+                    ..load_options(&args)
+                };
+                let config_files = config_files(&args, &mut cmd);
+                let config = loader::load(&config_files, &args.paths, &options)
+                    .unwrap_or_else(|e| exit_with_load_error(e, &args, &mut cmd));
+                let profiles = profiles(&config_files[0], &args, &mut cmd);
+                match format {
+                    DocsFormat::Markdown => print!("{}", docs::markdown(&config, &profiles)),
+                    DocsFormat::Html => print!("{}", docs::html(&config, &profiles)),
+                }
+            }
+            Command::Fmt {
+                files,
+                check,
+                sort_keys,
+                indent,
+                whitelist,
+            } => {
+                let mut files = files.clone();
+                if files.is_empty() && !whitelist {
+                    files = config_files(&args, &mut cmd);
+                }
+                if *whitelist {
+                    let path = json_env_config_dir_path(args.silent).join("whitelist.json");
+                    if path.exists() {
+                        files.push(path.to_string_lossy().to_string());
+                    }
+                }
+                let style = format::Style {
+                    indent: " ".repeat(*indent),
+                    sort_keys: *sort_keys,
+                };
+                if !format_files(&files, &style, *check, &args, &mut cmd) {
+                    process::exit(1);
+                }
+            }
+            Command::Shell {
+                profile,
+                prompt,
+                force,
+            } => run_shell(profile, *prompt, *force, &args, &mut cmd),
+            Command::Scan {
+                paths,
+                allowed_keys,
+                allowed_files,
+            } => {
+                if !scan_files(paths, allowed_keys, allowed_files, &args) {
+                    process::exit(1);
+                }
+            }
+            Command::Encrypt { keys, file, path } => {
+                let mut own_keys = crypt::Keys::default();
+                edit_file(file, path, &args, &mut cmd, |contents, object_path| {
+                    crypt::encrypt_in(contents, object_path, keys, &mut own_keys).map(Some)
+                });
+            }
+            Command::Decrypt { keys, file, path } => {
+                let mut own_keys = crypt::Keys::default();
+                edit_file(file, path, &args, &mut cmd, |contents, object_path| {
+                    crypt::decrypt_in(contents, object_path, keys, &mut own_keys).map(Some)
+                });
+            }
+            Command::Edit { file } => {
+                let file = file
+                    .clone()
+                    .unwrap_or_else(|| config_files(&args, &mut cmd).remove(0));
+                if let Err(e) = edit::edit(Path::new(&file), &mut crypt::Keys::default()) {
+                    if !args.silent {
+                        eprintln!("{e:#}");
+                    }
+                    process::exit(1);
+                }
+            }
+            Command::Keygen => {
+                let Some(keys_file) = crypt::keys_file() else {
+                    if !args.silent {
+                        eprintln!("Could not determine home directory");
+                    }
+                    process::exit(1);
+                };
+                match crypt::generate(&keys_file) {
+                    Ok(public) => println!("{public}"),
+                    Err(e) => {
+                        if !args.silent {
+                            eprintln!("Could not save the key to '{}': {e}", keys_file.display());
+                        }
+                        process::exit(1);
+                    }
+                }
+            }
+            Command::Get { key } => {
+                let config = load_vars(&args, &mut cmd);
+                match config.vars.get(key) {
+                    Some(var) => println!("{}", var.value),
+                    None => {
+                        if !args.silent {
+                            eprintln!("'{key}' is not defined in any config file");
+                        }
+                        process::exit(1);
+                    }
+                }
+            }
+            Command::Set {
+                key,
+                value,
+                json,
+                file,
+                path,
+            } => {
+                let value = match (value, json) {
+                    (Some(value), _) => serde_json::Value::String(value.clone()),
+                    (None, Some(json)) => match serde_json::from_str(json) {
+                        Ok(value) => value,
+                        Err(e) => exit_with_load_error(
+                            LoadError::Argument(format!("'{json}' is not valid JSON: {e}")),
+                            &args,
+                            &mut cmd,
+                        ),
+                    },
+                    (None, None) => unreachable!("clap requires a value or --json"),
+                };
+                edit_file(file, path, &args, &mut cmd, |contents, object_path| {
+                    patch::set(contents, object_path, key, &value).map(Some)
+                });
+            }
+            Command::Unset { key, file, path } => {
+                let changed = edit_file(file, path, &args, &mut cmd, |contents, object_path| {
+                    patch::unset(contents, object_path, key)
+                });
+                if !changed {
+                    if !args.silent {
+                        eprintln!("'{key}' is not defined in that file");
+                    }
+                    process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
     if args.executable.is_empty() && !args.export {
         if args.silent {
             process::exit(1);
@@ -204,84 +742,710 @@ fn main() {
         .exit();
     }
 
-    if args.config_files.is_empty() {
-        let env_json = find_env_file();
-        if let Some(path) = env_json {
-            args.config_files.push(path.to_str().unwrap().to_string());
-        } else if args.silent {
-            process::exit(1);
-        } else {
-            cmd.error(
-                ErrorKind::TooFewValues,
-                "You need to provide the name of a config file or be in a directory with a .env.json file in it or one of it's parents",
-            )
-                .exit();
+    let config = load_checked(config_files(&args, &mut cmd), &args.paths, &args, &mut cmd);
+
+    if args.export {
+        for (k, var) in &config.vars {
+            if args.why {
+                println!("{}", explain::comment(k, var));
+            }
+            if let Some(statement) = change::export_statement(k, &var.change, &var.value) {
+                println!("{statement}");
+            }
         }
+        return;
     }
 
-    // Read the config files, and parse them as JSON
-    for (i, file_name) in args.config_files.iter().enumerate() {
-        let Ok(mut file) = File::open(file_name) else {
+    run(
+        config,
+        &args.executable[0],
+        &args.executable[1..],
+        &[],
+        &args,
+    );
+}
+
+/// Run `program` with the variables from `config` and `extra_env`, as the options in `args` say: with secrets as
+/// files, a clean environment, masked output and expanded arguments.
+fn run(
+    config: Config,
+    program: &str,
+    program_args: &[String],
+    extra_env: &[(String, String)],
+    args: &Args,
+) {
+    let secret_values: Vec<String> = config
+        .vars
+        .iter()
+        .filter(|(key, var)| config.is_secret(key) && var.change != Change::Unset)
+        .map(|(_, var)| var.value.clone())
+        .collect();
+    // Only values that are flagged, encrypted or resolved become files, a name that looks secret is not enough
+    let flagged_keys: Vec<String> = config
+        .meta
+        .iter()
+        .filter(|(_, meta)| meta.secret)
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut env_vars = config.vars;
+
+    // The files are removed when this goes out of scope, after the executable has finished
+    let _secret_files = if args.secrets_as_files {
+        match SecretFiles::write(&mut env_vars, &flagged_keys) {
+            Ok(files) => {
+                // Ctrl+C stops the program, while json_env waits for it and removes the files afterwards
+                if let Err(e) = ctrlc::set_handler(|| {}) {
+                    if !args.silent {
+                        eprintln!(
+                            "Warning: could not handle Ctrl+C, the secrets may not be removed: {e}"
+                        );
+                    }
+                }
+                Some(files)
+            }
+            Err(e) => {
+                if !args.silent {
+                    eprintln!("Could not write the secrets to files: {e}");
+                }
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut masked = Vec::new();
+    if args.mask_output {
+        masked = secret_values;
+        if env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true") {
+            for command in mask::github_commands(&masked) {
+                println!("{command}");
+            }
+        }
+    }
+
+    let kept = args.clean_env.then(|| {
+        let mut kept = args.keep.clone();
+        kept.extend(
+            config
+                .keep
+                .into_iter()
+                .filter(|key| !args.keep.contains(key)),
+        );
+        kept
+    });
+
+    let mut program_args = program_args.to_vec();
+    if args.expand_args {
+        program_args = program_args
+            .iter()
+            .map(|arg| {
+                interpolate::interpolate(arg, |name| {
+                    env_vars
+                        .get(name)
+                        .filter(|var| var.change != Change::Unset)
+                        .map(|var| var.value.clone())
+                })
+            })
+            .collect();
+    }
+
+    execute(
+        &env_vars,
+        extra_env,
+        program,
+        &program_args,
+        &masked,
+        kept.as_deref(),
+    )
+}
+
+/// Load the config files for running a program: ask for missing values, validate the variables and check them
+/// against the policy. Exits on errors.
+fn load_checked(
+    config_files: Vec<String>,
+    paths: &[String],
+    args: &Args,
+    cmd: &mut clap::Command,
+) -> Config {
+    let mut config = load_config(config_files.clone(), paths, args, cmd);
+    prompt_missing(&mut config, &config_files[0], args);
+    validate(&config, args);
+    check_policy(&config.vars, args);
+    config
+}
+
+/// Start the user's shell with the variables from the config files, and the profile if one is given.
+fn run_shell(
+    profile: &Option<String>,
+    prompt: bool,
+    force: bool,
+    args: &Args,
+    cmd: &mut clap::Command,
+) {
+    // The output of an interactive shell cannot be piped, and it has no arguments to expand
+    for (used, flag) in [
+        (args.mask_output, "--mask-output"),
+        (args.expand_args, "--expand-args"),
+    ] {
+        if used {
             if args.silent {
-                return;
+                process::exit(1);
             }
             cmd.error(
-                ErrorKind::InvalidValue,
-                format!("Could not open '{file_name}'"),
-            ).exit();
+                ErrorKind::ArgumentConflict,
+                format!("{flag} cannot be used with 'shell'"),
+            )
+            .exit();
+        }
+    }
+    if let Ok(active) = env::var(shell::ACTIVE_VARIABLE) {
+        if !force {
+            if !args.silent {
+                eprintln!(
+                    "This is already a json_env shell for '{active}'. Exit it first, or use --force to start another one."
+                );
+            }
+            process::exit(1);
+        }
+    }
+    let (config_files, paths) =
+        with_profile(config_files(args, cmd), &args.paths, profile, args, cmd);
+    let config = load_checked(config_files.clone(), &paths, args, cmd);
+    let active = fs::canonicalize(&config_files[0])
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| config_files[0].clone());
+    let program = shell::user_shell();
+    let mut launch = None;
+    if prompt {
+        let prefix = match profile {
+            Some(profile) => format!("(json_env:{profile}) "),
+            None => "(json_env) ".to_string(),
         };
-        let mut contents = String::new();
-        let json_path = match args.paths.get(i) {
-            Some(p) => p,
-            None => "$",
+        match shell::Launch::with_prompt(&program, &prefix) {
+            Ok(Some(with_prompt)) => launch = Some(with_prompt),
+            Ok(None) => {
+                if !args.silent {
+                    eprintln!("Warning: the prompt of '{program}' cannot be changed");
+                }
+            }
+            Err(e) => {
+                if !args.silent {
+                    eprintln!("Warning: could not change the prompt: {e}");
+                }
+            }
+        }
+    }
+    let launch = launch.unwrap_or_else(|| shell::Launch::plain(&program));
+    let mut extra_env = launch.env.clone();
+    extra_env.push((shell::ACTIVE_VARIABLE.to_string(), active));
+    run(config, &launch.program, &launch.args, &extra_env, args);
+}
+
+/// Add the `.env.<profile>.json` file next to the first config file after it, and `$` as its JSON path, so that the
+/// other `paths` stay with their files. Exits if the profile does not exist.
+fn with_profile(
+    mut config_files: Vec<String>,
+    paths: &[String],
+    profile: &Option<String>,
+    args: &Args,
+    cmd: &mut clap::Command,
+) -> (Vec<String>, Vec<String>) {
+    let mut paths = paths.to_vec();
+    let Some(profile) = profile else {
+        return (config_files, paths);
+    };
+    let file = Path::new(&config_files[0]).with_file_name(format!(".env.{profile}.json"));
+    if !file.exists() {
+        exit_with_load_error(
+            LoadError::Argument(format!(
+                "There is no profile '{profile}', '{}' does not exist",
+                file.display()
+            )),
+            args,
+            cmd,
+        );
+    }
+    config_files.insert(1, file.to_string_lossy().to_string());
+    // Files without a path use `$`, so only a path that belongs to a later file has to be moved
+    if paths.len() > 1 {
+        paths.insert(1, "$".to_string());
+    }
+    (config_files, paths)
+}
+
+/// The profiles next to `config_file`, that is the `.env.<profile>.json` files other than the local and the example
+/// file, sorted by name.
+fn profiles(config_file: &str, args: &Args, cmd: &mut clap::Command) -> Vec<docs::Profile> {
+    let directory = Path::new(config_file)
+        .parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut profiles = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name == prompt::LOCAL_FILE || file_name == ".env.example.json" {
+            continue;
+        }
+        let Some(name) = file_name
+            .strip_prefix(".env.")
+            .and_then(|name| name.strip_suffix(".json"))
+            .filter(|name| !name.is_empty())
+        else {
+            continue;
         };
-        if file.read_to_string(&mut contents).is_ok() {
-            match parse_and_extract(&contents, json_path) {
-                Ok(val) => {
-                    if val.is_empty() {
-                        if args.silent {
-                            return;
-                        }
-                        cmd.error(
-                            ErrorKind::InvalidValue,
-                            format!("There is nothing in file '{file_name}' at path '{json_path}'"),
-                        )
-                        .exit();
-                    }
-                    add_values_to_map(&val, args.expand, &mut env_vars);
+        let layers = read_all_layers(
+            &[entry.path().to_string_lossy().to_string()],
+            &[],
+            args,
+            cmd,
+        );
+        let keys = layers
+            .iter()
+            .filter_map(|layer| layer.value.as_object())
+            .flat_map(|object| object.keys())
+            .filter(|key| !loader::is_reserved(key))
+            .cloned()
+            .collect();
+        profiles.push(docs::Profile {
+            name: name.to_string(),
+            keys,
+        });
+    }
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    profiles
+}
+
+/// Load the config files given on the command line, or the closest .env.json file. Exits on errors.
+fn load_vars(args: &Args, cmd: &mut clap::Command) -> Config {
+    let config_files = config_files(args, cmd);
+    load_config(config_files, &args.paths, args, cmd)
+}
+
+/// The config files given with `-c`, or the closest .env.json file and the .env.local.json file next to it.
+/// Exits if there is none.
+fn config_files(args: &Args, cmd: &mut clap::Command) -> Vec<String> {
+    if !args.config_files.is_empty() {
+        return args.config_files.clone();
+    }
+    if let Some(path) = find_env_file() {
+        let mut files = vec![path.to_str().unwrap().to_string()];
+        let local = path.with_file_name(prompt::LOCAL_FILE);
+        if local.exists() {
+            files.push(local.to_str().unwrap().to_string());
+        }
+        return files;
+    }
+    if args.silent {
+        process::exit(1);
+    }
+    cmd.error(
+        ErrorKind::TooFewValues,
+        "You need to provide the name of a config file or be in a directory with a .env.json file in it or one of it's parents",
+    )
+    .exit();
+}
+
+fn load_options(args: &Args) -> Options {
+    Options {
+        expand: args.expand,
+        lenient: args.lenient,
+        silent: args.silent,
+        strict: args.strict,
+        no_override: args.no_override,
+        decrypt: true,
+        allow_commands: Some(allow_commands),
+    }
+}
+
+fn load_config(
+    config_files: Vec<String>,
+    paths: &[String],
+    args: &Args,
+    cmd: &mut clap::Command,
+) -> Config {
+    loader::load(&config_files, paths, &load_options(args))
+        .unwrap_or_else(|e| exit_with_load_error(e, args, cmd))
+}
+
+/// Print a clap error and exit. If a subcommand was given, it may have been meant as a program that json_env ran
+/// before the subcommand was added, so also say how to run the program.
+fn exit_with_parse_error(error: clap::Error) -> ! {
+    if matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp
+            | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+            | ErrorKind::DisplayVersion
+    ) {
+        error.exit();
+    }
+    let _ = error.print();
+    let subcommand = env::args().skip(1).find(|arg| {
+        Args::command()
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == arg)
+    });
+    if let Some(subcommand) = subcommand {
+        eprintln!("To run the program '{subcommand}' instead of the subcommand, use 'json_env -- {subcommand}'");
+    }
+    // Like clap, exit with 2 for usage errors
+    process::exit(2);
+}
+
+fn exit_with_load_error(error: LoadError, args: &Args, cmd: &mut clap::Command) -> ! {
+    if args.silent {
+        process::exit(1);
+    }
+    match error {
+        LoadError::Argument(message) => cmd.error(ErrorKind::InvalidValue, message).exit(),
+        LoadError::Report(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Read the layers of all config files, like `load_config` but without merging them.
+fn read_all_layers(
+    config_files: &[String],
+    paths: &[String],
+    args: &Args,
+    cmd: &mut clap::Command,
+) -> Vec<Layer> {
+    let mut layers = Vec::new();
+    for (i, file_name) in config_files.iter().enumerate() {
+        let json_path = paths.get(i).map(String::as_str).unwrap_or("$");
+        match loader::read_layers(file_name, json_path, &load_options(args)) {
+            Ok(file_layers) => layers.extend(file_layers),
+            Err(e) => exit_with_load_error(e, args, cmd),
+        }
+    }
+    layers
+}
+
+/// Print the secrets found in the files in `paths`, and return `true` if there are none.
+fn scan_files(
+    paths: &[String],
+    allowed_keys: &[String],
+    allowed_files: &[String],
+    args: &Args,
+) -> bool {
+    let paths = match paths.is_empty() {
+        true => vec![".".to_string()],
+        false => paths.to_vec(),
+    };
+    let files = scan::find_files(&paths).unwrap_or_else(|e| {
+        if !args.silent {
+            eprintln!("Could not search for config files: {e}");
+        }
+        process::exit(1);
+    });
+    let mut clean = true;
+    let mut findings = Vec::new();
+    for file in files {
+        if allowed_files.iter().any(|allowed| file.ends_with(allowed)) {
+            continue;
+        }
+        let file = file.to_string_lossy().to_string();
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                if !args.silent {
+                    eprintln!("Could not read '{file}': {e}");
                 }
-                Err(e) => {
-                    if args.silent {
-                        return;
+                clean = false;
+                continue;
+            }
+        };
+        for json_path in &args.paths {
+            match loader::parse_layers(&file, &contents, json_path, &load_options(args)) {
+                Ok(layers) => findings.extend(scan::scan_layers(&layers, allowed_keys)),
+                // The file has nothing at this path, so there is nothing to scan
+                Err(LoadError::Argument(_)) => {}
+                Err(LoadError::Report(message)) => {
+                    if !args.silent {
+                        eprintln!("{message}");
                     }
-                    eprintln!("Error while parsing json or jsonpath: {e} in '{file_name}'");
-                    process::exit(1);
+                    clean = false;
                 }
             }
-        } else {
-            if args.silent {
-                return;
+        }
+    }
+    if !args.silent {
+        for finding in &findings {
+            let line = finding
+                .line
+                .map(|line| format!(":{line}"))
+                .unwrap_or_default();
+            println!(
+                "{}{line}: '{}' looks like a secret ({})",
+                finding.file, finding.key, finding.reason
+            );
+        }
+        if !findings.is_empty() {
+            eprintln!(
+                "Possible secrets found: {}. Encrypt them with `json_env encrypt` or allow them with --allow",
+                findings.len()
+            );
+        }
+    }
+    clean && findings.is_empty()
+}
+
+/// Format `files`, or with `check` only report the ones that are not formatted. Returns whether all of them
+/// were already formatted.
+fn format_files(
+    files: &[String],
+    style: &format::Style,
+    check: bool,
+    args: &Args,
+    cmd: &mut clap::Command,
+) -> bool {
+    let mut formatted = true;
+    for file in files {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(e) => exit_with_load_error(
+                LoadError::Argument(format!("Could not read '{file}': {e}")),
+                args,
+                cmd,
+            ),
+        };
+        let canonical = match format::format(&contents, style) {
+            Ok(canonical) => canonical,
+            Err(e) => exit_with_load_error(
+                LoadError::Report(Diagnostic::from_json_error(&e, file, &contents).to_string()),
+                args,
+                cmd,
+            ),
+        };
+        if canonical == contents {
+            continue;
+        }
+        formatted = false;
+        if check {
+            if !args.silent {
+                println!("'{file}' is not formatted");
             }
-            cmd.error(
-                ErrorKind::InvalidValue,
-                format!("Could not read JSON in '{file_name}'"),
-            )
-            .exit();
+        } else if let Err(e) = fs::write(file, canonical) {
+            if !args.silent {
+                eprintln!("Could not write '{file}': {e}");
+            }
+            process::exit(1);
         }
     }
+    formatted || !check
+}
 
-    if args.export {
-        for (k, v) in &env_vars {
-            println!("export {k}=\"{v}\"");
+/// Change a config file with `change`, which gets its contents and the JSON path of the object to edit and
+/// returns the new contents, or `None` if there is nothing to change. Exits on errors.
+/// Returns whether the file was changed.
+fn edit_file(
+    file: &Option<String>,
+    path: &Option<String>,
+    args: &Args,
+    cmd: &mut clap::Command,
+    change: impl FnOnce(&str, &str) -> anyhow::Result<Option<String>>,
+) -> bool {
+    let file = file
+        .clone()
+        .unwrap_or_else(|| config_files(args, cmd).remove(0));
+    let object_path = path.as_ref().unwrap_or(&args.paths[0]);
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) => exit_with_load_error(
+            LoadError::Argument(format!("Could not read '{file}': {e}")),
+            args,
+            cmd,
+        ),
+    };
+    let changed = match change(&contents, object_path) {
+        Ok(Some(changed)) => changed,
+        Ok(None) => return false,
+        Err(e) => {
+            let message = match e.downcast_ref::<json::ParseError>() {
+                Some(e) => Diagnostic::from_json_error(e, &file, &contents).to_string(),
+                None => format!("Could not change '{file}': {e}"),
+            };
+            exit_with_load_error(LoadError::Report(message), args, cmd)
+        }
+    };
+    if let Err(e) = fs::write(&file, changed) {
+        if !args.silent {
+            eprintln!("Could not write '{file}': {e}");
+        }
+        process::exit(1);
+    }
+    true
+}
+
+/// Exit if the config files set variables that the policy denies, unless the user confirms them. In silent mode
+/// or without a terminal to ask on, they are refused.
+fn check_policy(vars: &Vars, args: &Args) {
+    let policy = Policy::from_config().unwrap_or_else(|e| {
+        if !args.silent {
+            eprintln!("{e:#}");
+        }
+        process::exit(1);
+    });
+    let violations = policy.violations(vars);
+    if violations.is_empty() {
+        return;
+    }
+    if !args.silent {
+        for (file, keys) in &violations {
+            eprintln!(
+                "'{file}' sets {}, which can change what programs run",
+                keys.join(", ")
+            );
         }
+    }
+    if policy.action == policy::Action::Confirm
+        && !args.silent
+        && io::stdin().is_terminal()
+        && Confirm::new()
+            .with_prompt("Set them anyway?")
+            .default(false)
+            .interact()
+            .unwrap_or(false)
+    {
         return;
     }
+    if !args.silent {
+        let config_file = policy::config_file().unwrap_or_default();
+        eprintln!(
+            "Refused to set them, they can be allowed in '{}'",
+            config_file.display()
+        );
+    }
+    process::exit(1);
+}
 
-    execute(
-        &env_vars,
-        &args.executable[0],
-        &(args.executable[1..]).to_vec(),
-    )
+/// Whether the `$cmd` objects and named references for `keys` in `file` may run. Files that are whitelisted or
+/// trusted by the policy may run them, for other files the user is asked. In silent mode or without a terminal to
+/// ask on, they are refused.
+fn allow_commands(file: &str, keys: &[String], silent: bool) -> bool {
+    let path = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    if is_whitelisted(&path) || Policy::from_config().is_ok_and(|policy| policy.is_trusted(file)) {
+        return true;
+    }
+    if silent || !io::stdin().is_terminal() {
+        return false;
+    }
+    eprintln!(
+        "'{file}' runs commands to get the values of {}",
+        keys.join(", ")
+    );
+    Confirm::new()
+        .with_prompt("Run them?")
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+/// Ask for the values of required variables that are missing, and offer to save them next to `config_file`.
+/// Does nothing in silent mode or if there is no terminal to ask on.
+fn prompt_missing(config: &mut Config, config_file: &str, args: &Args) {
+    let keys = prompt::missing_keys(config);
+    if keys.is_empty() || args.silent || !io::stdin().is_terminal() {
+        return;
+    }
+    eprintln!("These variables need a value: {}", keys.join(", "));
+    let answers = match prompt::ask(config, &keys) {
+        Ok(answers) => answers,
+        Err(e) => {
+            eprintln!("Could not read the values: {e}");
+            process::exit(1);
+        }
+    };
+    let directory = Path::new(config_file).parent().unwrap_or(Path::new(""));
+    let local = directory.join(prompt::LOCAL_FILE);
+    let mut source = "<prompt>".to_string();
+    if Confirm::new()
+        .with_prompt(format!("Save the values to '{}'?", local.display()))
+        .interact()
+        .unwrap_or(false)
+    {
+        match prompt::save(&local, &answers) {
+            Ok(()) => source = local.to_string_lossy().to_string(),
+            Err(e) => eprintln!("Could not save the values to '{}': {e}", local.display()),
+        }
+    }
+    prompt::apply(config, &answers, &source);
+}
+
+/// Validate the variables against their definitions and the schema given with `--schema` or in the config
+/// files. Exits on violations.
+fn validate(config: &Config, args: &Args) {
+    let mut violations = schema::validate_meta(config);
+    let mut schema_path = args.schema.as_ref().or(config.schema.as_ref());
+    if let Some(path) = schema_path.filter(|path| path.contains("://")) {
+        if !args.silent {
+            eprintln!(
+                "Warning: remote schemas are not supported, skipping validation against '{path}'"
+            );
+        }
+        schema_path = None;
+    }
+    if let Some(schema_path) = schema_path {
+        let contents = match fs::read_to_string(schema_path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                if !args.silent {
+                    eprintln!("Could not read schema '{schema_path}': {e}");
+                }
+                process::exit(1);
+            }
+        };
+        let schema = match json::parse(&contents) {
+            Ok(schema) => schema.to_value(),
+            Err(e) => {
+                if !args.silent {
+                    eprintln!(
+                        "{}",
+                        Diagnostic::from_json_error(&e, schema_path, &contents)
+                    );
+                }
+                process::exit(1);
+            }
+        };
+        violations.extend(schema::validate(&schema, config));
+    }
+    if violations.is_empty() {
+        return;
+    }
+    if !args.silent {
+        eprintln!("The variables are not valid:");
+        for violation in &violations {
+            eprintln!("  {violation}");
+        }
+    }
+    process::exit(1);
+}
+
+/// The variables, with the values of secrets masked unless `reveal` is set.
+fn shown_vars(config: &Config, reveal: bool) -> Vars {
+    config
+        .vars
+        .iter()
+        .map(|(key, var)| {
+            if !reveal && config.is_secret(key) {
+                (key.clone(), var.masked())
+            } else {
+                (key.clone(), var.clone())
+            }
+        })
+        .collect()
+}
+
+/// Only the values of the variables, without where they came from.
+fn values(vars: &Vars) -> BTreeMap<String, String> {
+    vars.iter()
+        .map(|(key, var)| (key.clone(), var.value.clone()))
+        .collect()
 }
 
 fn whitelist(config_path: &Path) {
@@ -295,10 +1459,7 @@ fn whitelist(config_path: &Path) {
 
     let contents = read_or_create_empty(&config_dir);
 
-    let mut whitelist = match serde_json::from_str::<Vec<String>>(&contents) {
-        Ok(w) => w,
-        Err(_) => Vec::new(),
-    };
+    let mut whitelist = serde_json::from_str::<Vec<String>>(&contents).unwrap_or_default();
     let config_path_str = config_path.to_str().unwrap();
     for path in &whitelist {
         if path == config_path_str {
@@ -308,17 +1469,16 @@ fn whitelist(config_path: &Path) {
     }
     whitelist.push(config_path_str.to_string());
     let Ok(whitelist_json) = serde_json::to_string_pretty(&whitelist) else {
-            return;
-        };
+        return;
+    };
 
     let Ok(mut output_file) = OpenOptions::new()
         .write(true)
         .truncate(true)
-        .open(config_dir.clone()) else
-    {
-            println!("Could not open whitelist file");
-            process::exit(1);
-
+        .open(config_dir.clone())
+    else {
+        println!("Could not open whitelist file");
+        process::exit(1);
     };
 
     if output_file
@@ -338,6 +1498,7 @@ fn read_or_create_empty(config_file: &Path) -> String {
                 .write(true)
                 .read(true)
                 .create(true)
+                .truncate(false)
                 .open(config_file)
             {
                 Ok(file) => file,
@@ -378,12 +1539,8 @@ fn is_whitelisted(config_path: &Path) -> bool {
 }
 
 fn json_env_config_dir_path(silent: bool) -> PathBuf {
-    match home_dir() {
-        Some(mut path) => {
-            path.push(".config");
-            path.push("json_env");
-            path
-        }
+    match config_dir::path() {
+        Some(path) => path,
         None => {
             if !silent {
                 println!("Could not determine home directory");
@@ -449,7 +1606,10 @@ fn install_shell_completion(silent: bool) {
     if !shell_config_path.exists() {
         let Ok(mut file) = File::create(&shell_config_path) else {
             if !silent {
-                println!("Could not create file '{}'", shell_config_path.to_str().unwrap());
+                println!(
+                    "Could not create file '{}'",
+                    shell_config_path.to_str().unwrap()
+                );
             }
             process::exit(1);
         };
@@ -502,7 +1662,10 @@ fn install_shell_completion(silent: bool) {
     if !json_env_config_dir_path.exists() {
         let Ok(mut file) = File::create(&json_env_config_dir_path) else {
             if !silent {
-                println!("Could not create file '{}'", json_env_config_dir_path.to_str().unwrap());
+                println!(
+                    "Could not create file '{}'",
+                    json_env_config_dir_path.to_str().unwrap()
+                );
             }
             process::exit(1);
         };
@@ -533,69 +1696,60 @@ fn get_shell() -> Result<Shell, String> {
     }
 }
 
-fn parse_and_extract(json_str: &str, path: &str) -> Result<Vec<Value>> {
-    let finder = JsonPathFinder::from_str(json_str, path).map_err(Error::msg)?;
-    finder
-        .find()
-        .as_array()
-        .cloned()
-        .ok_or_else(|| Error::msg("Json path does not point to valid object."))
-}
-
-/// Execute the given command with the given environment variables.
-fn execute(vars: &HashMap<String, String>, command: &str, args: &Vec<String>) {
-    match Command::new(command).envs(vars).args(args).spawn() {
+/// Execute the given command with the given environment variables. If `secrets` is not empty, its output is
+/// piped through json_env and the secrets are masked. If `kept` is given, the environment is cleared first and
+/// only the variables in it are inherited.
+fn execute(
+    vars: &Vars,
+    extra_env: &[(String, String)],
+    command: &str,
+    args: &Vec<String>,
+    secrets: &[String],
+    kept: Option<&[String]>,
+) {
+    let mut child_command = process::Command::new(command);
+    if let Some(kept) = kept {
+        child_command.env_clear();
+        for key in kept {
+            if let Some(value) = env::var_os(key) {
+                child_command.env(key, value);
+            }
+        }
+    }
+    for (key, var) in vars {
+        match var.change {
+            Change::Unset => child_command.env_remove(key),
+            _ => child_command.env(key, &var.value),
+        };
+    }
+    child_command.envs(extra_env.iter().map(|(key, value)| (key, value)));
+    child_command.args(args);
+    if !secrets.is_empty() {
+        child_command
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+    }
+    match child_command.spawn() {
         Err(e) => {
             eprintln!("Could not start executable '{command}': {e}");
         }
         Ok(mut child) => {
+            // Both streams are copied as their output arrives, which keeps them interleaved
+            let copies = [
+                child.stdout.take().map(|stdout| {
+                    let masker = mask::Masker::new(secrets);
+                    thread::spawn(move || mask::copy(stdout, io::stdout(), masker))
+                }),
+                child.stderr.take().map(|stderr| {
+                    let masker = mask::Masker::new(secrets);
+                    thread::spawn(move || mask::copy(stderr, io::stderr(), masker))
+                }),
+            ];
             if let Err(e) = child.wait() {
                 eprintln!("Error when running executable '{command}: {e}");
             }
-        }
-    }
-}
-
-fn add_values_to_map(
-    values: &Vec<Value>,
-    should_expand: bool,
-    str_map: &mut HashMap<String, String>,
-) {
-    for value in values {
-        if value.is_object() {
-            let in_val = value.as_object().unwrap();
-            for (key, val) in in_val {
-                let mut val_str = "".to_string();
-                if val.is_array() {
-                    val_str = val.to_string();
-                }
-                if val.is_boolean() {
-                    val_str = val.to_string();
-                }
-                if val.is_object() {
-                    val_str = val.to_string();
-                }
-                if val.is_null() {
-                    val_str = val.to_string();
-                }
-                if val.is_number() {
-                    val_str = val.to_string();
-                }
-                if val.is_string() {
-                    val_str = val.as_str().unwrap().to_string(); // the as_str is needed, because we get quotes otherwise
-                }
-                if should_expand {
-                    let mut expanded_val = val_str.clone();
-                    for (env_key, env_value) in env::vars() {
-                        let env_key_dollar = format!("${env_key}");
-                        if val_str.contains(&env_key_dollar) {
-                            expanded_val = val_str.replace(&env_key_dollar, &env_value);
-                        }
-                    }
-                    str_map.insert(key.to_string(), expanded_val);
-                } else {
-                    str_map.insert(key.to_string(), val_str);
-                }
+            for copy in copies.into_iter().flatten() {
+                let _ = copy.join();
             }
         }
     }
@@ -620,15 +1774,20 @@ fn find_env_file() -> Option<PathBuf> {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use crate::loader::add_values_to_map;
+
+    fn parse_and_extract(json_str: &str, path: &str) -> Result<Vec<Layer>, LoadError> {
+        loader::parse_layers("test.json", json_str, path, &Options::default())
+    }
 
     #[test]
     fn parse_simple() {
         let simple_json = include_str!("../examples/simple/.env.json");
         let val = parse_and_extract(simple_json, "$");
         assert!(val.is_ok());
-        let mut env_vars: HashMap<String, String> = HashMap::new();
+        let mut env_vars = Vars::new();
         add_values_to_map(&val.unwrap(), false, &mut env_vars);
-        let node_env = env_vars.get("NODE_ENV");
+        let node_env = env_vars.get("NODE_ENV").map(|var| &var.value);
         assert!(node_env.is_some());
         assert_eq!(node_env.unwrap().to_string(), "DEV".to_string());
     }
@@ -639,9 +1798,9 @@ mod tests {
         env::set_var("FOO", "Bar");
         let val = parse_and_extract(extendable_json, "$");
         assert!(val.is_ok());
-        let mut env_vars: HashMap<String, String> = HashMap::new();
+        let mut env_vars = Vars::new();
         add_values_to_map(&val.unwrap(), true, &mut env_vars);
-        let node_env = env_vars.get("TEST");
+        let node_env = env_vars.get("TEST").map(|var| &var.value);
         assert!(node_env.is_some());
         assert_eq!(node_env.unwrap().to_string(), "Bar".to_string());
     }
@@ -652,9 +1811,9 @@ mod tests {
         env::set_var("FOO", "Bar");
         let val = parse_and_extract(extendable_json, "$");
         assert!(val.is_ok());
-        let mut env_vars: HashMap<String, String> = HashMap::new();
+        let mut env_vars = Vars::new();
         add_values_to_map(&val.unwrap(), true, &mut env_vars);
-        let node_env = env_vars.get("TEST");
+        let node_env = env_vars.get("TEST").map(|var| &var.value);
         assert!(node_env.is_some());
         assert!(node_env.unwrap().contains("Bar"));
     }
@@ -664,10 +1823,33 @@ mod tests {
         let nested_json = include_str!("../examples/nested/.env.json");
         let val = parse_and_extract(nested_json, "$.nested");
         assert!(val.is_ok());
-        let mut env_vars: HashMap<String, String> = HashMap::new();
+        let mut env_vars = Vars::new();
         add_values_to_map(&val.unwrap(), true, &mut env_vars);
-        let hello = env_vars.get("hello");
+        let hello = env_vars.get("hello").map(|var| &var.value);
         assert!(hello.is_some());
         assert_eq!(hello.unwrap(), "world");
     }
+
+    #[test]
+    fn profile_keeps_paths_with_their_files() {
+        let directory = env::temp_dir().join(format!("json_env-profile-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let main = directory.join(".env.json");
+        fs::write(directory.join(".env.x.json"), "{}").unwrap();
+        let main = main.to_string_lossy().to_string();
+        let args = Args::parse_from([
+            "json_env", "-c", &main, "-c", "b.json", "-p", "$", "-p", "$.V", "--", "true",
+        ]);
+        let (files, paths) = with_profile(
+            args.config_files.clone(),
+            &args.paths,
+            &Some("x".to_string()),
+            &args,
+            &mut Args::command(),
+        );
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(files[1], directory.join(".env.x.json").to_string_lossy());
+        assert_eq!(files[2], "b.json");
+        assert_eq!(paths, vec!["$", "$", "$.V"]);
+    }
 }
@@ -0,0 +1,133 @@
+//! Replacing secret values in the output of the child process with `****`.
+
+use std::io::{self, Read, Write};
+
+use crate::secret::MASK;
+
+/// Masks secrets in a stream that arrives in chunks. Bytes at the end of a chunk that could be the start of a
+/// secret are held back until the next chunk shows whether they are.
+#[derive(Debug, Default)]
+pub struct Masker {
+    /// Longest first, so that a secret that contains another one is masked as a whole.
+    secrets: Vec<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl Masker {
+    pub fn new(secrets: &[String]) -> Masker {
+        let mut secrets: Vec<Vec<u8>> = secrets
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.as_bytes().to_vec())
+            .collect();
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        secrets.dedup();
+        Masker {
+            secrets,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Mask the next chunk and return what can be written now.
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+        self.mask(false)
+    }
+
+    /// Mask and return everything that was held back, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.mask(true)
+    }
+
+    fn mask(&mut self, at_end: bool) -> Vec<u8> {
+        let buffer = std::mem::take(&mut self.pending);
+        let mut out = Vec::with_capacity(buffer.len());
+        let mut i = 0;
+        while i < buffer.len() {
+            let rest = &buffer[i..];
+            if let Some(secret) = self.secrets.iter().find(|s| rest.starts_with(s)) {
+                out.extend_from_slice(MASK.as_bytes());
+                i += secret.len();
+                continue;
+            }
+            if !at_end && self.secrets.iter().any(|s| s.starts_with(rest)) {
+                break;
+            }
+            out.push(buffer[i]);
+            i += 1;
+        }
+        self.pending = buffer[i..].to_vec();
+        out
+    }
+}
+
+/// Copy `input` to `output` with secrets masked, writing each chunk as soon as it arrives.
+pub fn copy(mut input: impl Read, mut output: impl Write, mut masker: Masker) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        output.write_all(&masker.push(&buffer[..read]))?;
+        output.flush()?;
+    }
+    output.write_all(&masker.finish())?;
+    output.flush()
+}
+
+/// `::add-mask::` commands that tell GitHub Actions to hide the secrets in its logs. Every line of a secret is
+/// masked on its own.
+pub fn github_commands(secrets: &[String]) -> Vec<String> {
+    secrets
+        .iter()
+        .flat_map(|s| s.lines())
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("::add-mask::{line}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask_chunks(secrets: &[&str], chunks: &[&str]) -> (Vec<String>, String) {
+        let secrets: Vec<String> = secrets.iter().map(|s| s.to_string()).collect();
+        let mut masker = Masker::new(&secrets);
+        let mut outputs: Vec<String> = chunks
+            .iter()
+            .map(|chunk| String::from_utf8(masker.push(chunk.as_bytes())).unwrap())
+            .collect();
+        outputs.push(String::from_utf8(masker.finish()).unwrap());
+        let joined = outputs.concat();
+        (outputs, joined)
+    }
+
+    #[test]
+    fn masks_across_chunks() {
+        let (outputs, joined) = mask_chunks(&["hunter2"], &["password: hun", "ter2\nhu", "man\n"]);
+        assert_eq!(outputs[0], "password: ");
+        assert_eq!(joined, "password: ****\nhuman\n");
+
+        let (_, joined) = mask_chunks(&["abc", "abcdef"], &["xabcdefx", "abc"]);
+        assert_eq!(joined, "x****x****");
+
+        // Held back bytes are written at the end even if the secret never completes
+        let (_, joined) = mask_chunks(&["hunter2"], &["hunt"]);
+        assert_eq!(joined, "hunt");
+    }
+
+    #[test]
+    fn copies_streams() {
+        let masker = Masker::new(&["s3cret".to_string()]);
+        let mut output = Vec::new();
+        copy("url=postgres://u:s3cret@db".as_bytes(), &mut output, masker).unwrap();
+        assert_eq!(output, b"url=postgres://u:****@db");
+        assert_eq!(
+            github_commands(&["a\nb".to_string()]),
+            vec!["::add-mask::a", "::add-mask::b"]
+        );
+    }
+}
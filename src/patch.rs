@@ -0,0 +1,229 @@
+//! Editing config files in place, changing only the text of the edited member so that whitespace, key order and
+//! indentation stay as they are.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+use crate::json::{self, Kind, Member, Node};
+use crate::loader;
+
+/// The value of `key` in the object at `object_path`, or its `$value` if it is a definition.
+pub fn get(source: &str, object_path: &str, key: &str) -> Result<Option<Value>> {
+    let document = json::parse(source)?;
+    let object = find_object(&document, object_path)?;
+    let Kind::Object(members) = &object.kind else {
+        unreachable!("find_object only returns objects");
+    };
+    Ok(members
+        .iter()
+        .rev()
+        .find(|m| m.key == key)
+        .map(|member| value_node(member).to_value()))
+}
+
+/// Set `key` in the object at `object_path` to `value`. If the key is defined with a `$value`, only that is replaced.
+/// Returns the new contents of the file.
+pub fn set(source: &str, object_path: &str, key: &str, value: &Value) -> Result<String> {
+    let document = json::parse(source)?;
+    let object = find_object(&document, object_path)?;
+    let Kind::Object(members) = &object.kind else {
+        unreachable!("find_object only returns objects");
+    };
+    let rendered = serde_json::to_string(value)?;
+    if let Some(member) = members.iter().rev().find(|m| m.key == key) {
+        let target = value_node(member);
+        return Ok(replace(
+            source,
+            target.span.start,
+            target.span.end,
+            &rendered,
+        ));
+    }
+    let entry = format!("{}: {rendered}", json::quote(key));
+    let Some(last) = members.last() else {
+        // An empty object gets the member on its own line, one level deeper than the object
+        let indent = line_indentation(source, object.span.start);
+        let unit = indentation_unit(&document, source);
+        let inner = format!("\n{indent}{unit}{entry}\n{indent}");
+        return Ok(replace(
+            source,
+            object.span.start + 1,
+            object.span.end - 1,
+            &inner,
+        ));
+    };
+    let before_key = &source[line_start(source, last.key_span.start)..last.key_span.start];
+    let insertion = if before_key.trim().is_empty() {
+        format!(",\n{before_key}{entry}")
+    } else {
+        format!(", {entry}")
+    };
+    Ok(replace(
+        source,
+        last.value.span.end,
+        last.value.span.end,
+        &insertion,
+    ))
+}
+
+/// Remove `key` from the object at `object_path`. Returns `None` if the key is not there.
+pub fn unset(source: &str, object_path: &str, key: &str) -> Result<Option<String>> {
+    let document = json::parse(source)?;
+    let object = find_object(&document, object_path)?;
+    let Kind::Object(members) = &object.kind else {
+        unreachable!("find_object only returns objects");
+    };
+    let Some(index) = members.iter().rposition(|m| m.key == key) else {
+        return Ok(None);
+    };
+    let member = &members[index];
+    let (start, end) = if let Some(next) = members.get(index + 1) {
+        // Remove up to the next key, which then takes the place and indentation of the removed one
+        (member.key_span.start, next.key_span.start)
+    } else if index > 0 {
+        // Remove the comma after the previous member, and everything up to the end of this one
+        (members[index - 1].value.span.end, member.value.span.end)
+    } else {
+        (object.span.start + 1, object.span.end - 1)
+    };
+    Ok(Some(replace(source, start, end, "")))
+}
+
+/// The node that holds the value of a member: its `$value` if it is a definition, else the member's value.
+fn value_node(member: &Member) -> &Node {
+    match &member.value.kind {
+        Kind::Object(fields) => fields
+            .iter()
+            .find(|f| f.key == loader::VALUE_KEY)
+            .map(|f| &f.value)
+            .unwrap_or(&member.value),
+        _ => &member.value,
+    }
+}
+
+/// Find the object that a simple JSON path like `$`, `$.Values` or `$['a'].b` points to.
+fn find_object<'a>(root: &'a Node, path: &str) -> Result<&'a Node> {
+    let mut node = root;
+    for key in member_path(path)? {
+        let Kind::Object(members) = &node.kind else {
+            bail!("'{path}' does not point to an object");
+        };
+        node = members
+            .iter()
+            .rev()
+            .find(|m| m.key == key)
+            .map(|m| &m.value)
+            .ok_or_else(|| anyhow!("'{path}' does not exist, there is no key '{key}'"))?;
+    }
+    match node.kind {
+        Kind::Object(_) => Ok(node),
+        _ => bail!("'{path}' does not point to an object"),
+    }
+}
+
+/// The keys in a JSON path made only of member names, e.g. `["a", "b"]` for `$.a['b']`.
+fn member_path(path: &str) -> Result<Vec<String>> {
+    let unsupported =
+        || anyhow!("only paths made of keys, like '$.Values', can be edited, not '{path}'");
+    let mut rest = path.strip_prefix('$').ok_or_else(unsupported)?;
+    let mut keys = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let key = &after_dot[..end];
+            if key.is_empty() || key == "*" {
+                return Err(unsupported());
+            }
+            keys.push(key.to_string());
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix("['") {
+            let end = after_bracket.find("']").ok_or_else(unsupported)?;
+            keys.push(after_bracket[..end].to_string());
+            rest = &after_bracket[end + 2..];
+        } else {
+            return Err(unsupported());
+        }
+    }
+    Ok(keys)
+}
+
+fn replace(source: &str, start: usize, end: usize, text: &str) -> String {
+    format!("{}{text}{}", &source[..start], &source[end..])
+}
+
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0)
+}
+
+/// The whitespace at the start of the line that contains `offset`.
+fn line_indentation(source: &str, offset: usize) -> &str {
+    let start = line_start(source, offset);
+    let line = &source[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// The indentation of one level, taken from the first member of the root object that is on its own line.
+fn indentation_unit(document: &Node, source: &str) -> String {
+    if let Kind::Object(members) = &document.kind {
+        if let Some(first) = members.first() {
+            let before = &source[line_start(source, first.key_span.start)..first.key_span.start];
+            let root_indent = line_indentation(source, document.span.start);
+            if before.trim().is_empty() && before.len() > root_indent.len() {
+                return before[root_indent.len()..].to_string();
+            }
+        }
+    }
+    "  ".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FILE: &str =
+        "{\n    \"B\": \"b\",\n    \"Values\": {\n        \"X\": 1\n    },\n    \"A\": \"a\"\n}\n";
+
+    #[test]
+    fn set_keeps_formatting() {
+        let changed = set(FILE, "$", "B", &json!("new")).unwrap();
+        assert_eq!(changed, FILE.replace("\"b\"", "\"new\""));
+
+        let added = set(FILE, "$.Values", "Y", &json!({"a": 1})).unwrap();
+        assert_eq!(
+            added,
+            FILE.replace("\"X\": 1\n", "\"X\": 1,\n        \"Y\": {\"a\":1}\n")
+        );
+
+        let inline = set("{\"A\": 1}", "$", "B", &json!(true)).unwrap();
+        assert_eq!(inline, "{\"A\": 1, \"B\": true}");
+
+        let empty = set("{\n    \"E\": {}\n}", "$.E", "K", &json!("v")).unwrap();
+        assert_eq!(empty, "{\n    \"E\": {\n        \"K\": \"v\"\n    }\n}");
+    }
+
+    #[test]
+    fn set_keeps_metadata() {
+        let source = "{\"P\": {\"$value\": \"x\", \"secret\": true}}";
+        let changed = set(source, "$", "P", &json!("y")).unwrap();
+        assert_eq!(changed, "{\"P\": {\"$value\": \"y\", \"secret\": true}}");
+    }
+
+    #[test]
+    fn unset_keeps_formatting() {
+        let first = unset(FILE, "$", "B").unwrap().unwrap();
+        assert_eq!(first, FILE.replace("    \"B\": \"b\",\n", ""));
+        let last = unset(FILE, "$", "A").unwrap().unwrap();
+        assert_eq!(last, FILE.replace(",\n    \"A\": \"a\"", ""));
+        let only = unset(FILE, "$.Values", "X").unwrap().unwrap();
+        assert!(only.contains("\"Values\": {},"));
+        assert_eq!(unset(FILE, "$", "NOPE").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_complex_paths() {
+        assert!(set(FILE, "$..X", "K", &json!(1)).is_err());
+        assert!(set(FILE, "$.Missing", "K", &json!(1)).is_err());
+        assert!(set(FILE, "$.B", "K", &json!(1)).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Variables that config files must not set without asking, because they change which code runs, like
+//! `LD_PRELOAD` or `PATH`.
+//!
+//! The policy is configured in `~/.config/json_env/policy.json`:
+//! `{"deny": ["PERL5OPT"], "allow": ["PATH"], "action": "confirm", "trusted_files": ["/home/me/app/.env.json"]}`.
+//! `deny` adds to the default keys and `allow` removes from them. Trusted files may set any key.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::config_dir;
+use crate::loader::{self, Vars};
+
+/// Keys that are denied unless the policy allows them.
+pub const DEFAULT_DENIED: &[&str] = &[
+    "LD_PRELOAD",
+    "LD_LIBRARY_PATH",
+    "DYLD_INSERT_LIBRARIES",
+    "DYLD_LIBRARY_PATH",
+    "PATH",
+    "BASH_ENV",
+    "ENV",
+    "PROMPT_COMMAND",
+    "NODE_OPTIONS",
+];
+
+/// What happens when a config file sets a denied key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Action {
+    /// Ask whether to go on, and refuse if there is no one to ask.
+    #[default]
+    Confirm,
+    Refuse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub denied: Vec<String>,
+    pub action: Action,
+    trusted_files: Vec<PathBuf>,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            denied: DEFAULT_DENIED.iter().map(|key| key.to_string()).collect(),
+            action: Action::default(),
+            trusted_files: Vec::new(),
+        }
+    }
+}
+
+/// The file in which the policy is configured.
+pub fn config_file() -> Option<PathBuf> {
+    config_dir::file("policy.json")
+}
+
+impl Policy {
+    /// The policy from `~/.config/json_env/policy.json`, or the default policy if there is no such file.
+    pub fn from_config() -> Result<Policy> {
+        let Some(file) = config_file().filter(|file| file.exists()) else {
+            return Ok(Policy::default());
+        };
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Could not read '{}'", file.display()))?;
+        let config: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("'{}' is not valid JSON", file.display()))?;
+        Policy::from_value(&config)
+            .with_context(|| format!("Invalid policy in '{}'", file.display()))
+    }
+
+    fn from_value(config: &Value) -> Result<Policy> {
+        let list = |name: &str| -> Result<Vec<String>> {
+            match config.get(name) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => Ok(s.clone()),
+                        _ => bail!("\"{name}\" must be a list of strings"),
+                    })
+                    .collect(),
+                Some(_) => bail!("\"{name}\" must be a list of strings"),
+            }
+        };
+        let mut policy = Policy::default();
+        let allowed = list("allow")?;
+        policy.denied.retain(|key| !allowed.contains(key));
+        for key in list("deny")? {
+            if !policy.denied.contains(&key) {
+                policy.denied.push(key);
+            }
+        }
+        policy.action = match config.get("action").and_then(Value::as_str) {
+            None | Some("confirm") => Action::Confirm,
+            Some("refuse") => Action::Refuse,
+            Some(other) => bail!("\"action\" must be \"confirm\" or \"refuse\", not \"{other}\""),
+        };
+        policy.trusted_files = list("trusted_files")?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        Ok(policy)
+    }
+
+    /// The denied keys in `vars`, by the file that sets them. Keys from trusted files and those that keep their
+    /// value from the environment are left out.
+    pub fn violations(&self, vars: &Vars) -> BTreeMap<String, Vec<String>> {
+        let mut violations: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (key, var) in vars {
+            if var.source.file == loader::ENVIRONMENT {
+                continue;
+            }
+            if self.denied.contains(key) && !self.is_trusted(&var.source.file) {
+                violations
+                    .entry(var.source.file.clone())
+                    .or_default()
+                    .push(key.clone());
+            }
+        }
+        violations
+    }
+
+    /// Whether `file` is one of the trusted files.
+    pub fn is_trusted(&self, file: &str) -> bool {
+        let Ok(file) = Path::new(file).canonicalize() else {
+            return false;
+        };
+        self.trusted_files
+            .iter()
+            .any(|trusted| trusted.canonicalize().is_ok_and(|trusted| trusted == file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Var;
+    use serde_json::json;
+
+    #[test]
+    fn finds_denied_keys() {
+        let policy = Policy::from_value(&json!({
+            "deny": ["PERL5OPT"],
+            "allow": ["PATH"],
+            "action": "refuse"
+        }))
+        .unwrap();
+        assert_eq!(policy.action, Action::Refuse);
+        let mut vars = Vars::new();
+        for (key, file) in [
+            ("PATH", ".env.json"),
+            ("LD_PRELOAD", ".env.json"),
+            ("PERL5OPT", ".env.local.json"),
+            ("NODE_ENV", ".env.json"),
+            ("DYLD_LIBRARY_PATH", loader::ENVIRONMENT),
+        ] {
+            vars.insert(key.to_string(), Var::test("", file, None));
+        }
+        let violations = policy.violations(&vars);
+        assert_eq!(
+            violations,
+            BTreeMap::from([
+                (".env.json".to_string(), vec!["LD_PRELOAD".to_string()]),
+                (".env.local.json".to_string(), vec!["PERL5OPT".to_string()]),
+            ])
+        );
+        assert!(Policy::from_value(&json!({"action": "ignore"})).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Asking for values that are required but missing, and saving the answers to a local override file.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use dialoguer::{Input, Password};
+
+use crate::change::Change;
+use crate::json::{self, Kind, Member, Node, Span};
+use crate::loader::{Config, Source, Var};
+
+/// The value the readme uses for keys that have to be set in another file.
+pub const PLACEHOLDER: &str = "TO_BE_OVERWRITTEN";
+
+/// The file next to `.env.json` that answers are saved to. It is loaded after `.env.json`.
+pub const LOCAL_FILE: &str = ".env.local.json";
+
+/// Keys that are required but not defined or unset, or whose value is still the placeholder.
+pub fn missing_keys(config: &Config) -> Vec<String> {
+    let mut keys: Vec<String> = config
+        .meta
+        .iter()
+        .filter(|(key, meta)| {
+            meta.required
+                && config
+                    .vars
+                    .get(*key)
+                    .is_none_or(|var| var.change == Change::Unset)
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    keys.extend(
+        config
+            .vars
+            .iter()
+            .filter(|(_, var)| var.value == PLACEHOLDER)
+            .map(|(key, _)| key.clone()),
+    );
+    keys.sort();
+    keys
+}
+
+/// Ask for the values of `keys` on the terminal. Secrets are read without echoing them.
+pub fn ask(config: &Config, keys: &[String]) -> io::Result<BTreeMap<String, String>> {
+    let mut answers = BTreeMap::new();
+    for key in keys {
+        let prompt = match config.meta.get(key).and_then(|m| m.description.as_ref()) {
+            Some(description) => format!("{key} ({description})"),
+            None => key.clone(),
+        };
+        let value = if config.is_secret(key) {
+            Password::new().with_prompt(prompt).interact()?
+        } else {
+            Input::<String>::new().with_prompt(prompt).interact_text()?
+        };
+        answers.insert(key.clone(), value);
+    }
+    Ok(answers)
+}
+
+/// Put the answers into the config, as if they came from `file`.
+pub fn apply(config: &mut Config, answers: &BTreeMap<String, String>, file: &str) {
+    for (key, value) in answers {
+        let var = Var {
+            value: value.clone(),
+            source: Source {
+                file: file.to_string(),
+                json_path: "$".to_string(),
+                line: None,
+            },
+            unexpanded: None,
+            overridden: Vec::new(),
+            change: Change::Set,
+        };
+        config.vars.insert(key.clone(), var);
+    }
+}
+
+/// Add the answers to the JSON object in `path`, creating the file if needed. Only the user can read a new file.
+/// Existing keys are replaced, the order of the others is kept.
+pub fn save(path: &Path, answers: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    let mut document = if path.exists() {
+        json::parse(&fs::read_to_string(path)?)?
+    } else {
+        Node {
+            kind: Kind::Object(Vec::new()),
+            span: Span { start: 0, end: 0 },
+        }
+    };
+    let Kind::Object(members) = &mut document.kind else {
+        anyhow::bail!("'{}' does not contain a JSON object", path.display());
+    };
+    for (key, value) in answers {
+        let value = Node {
+            kind: Kind::String(value.clone()),
+            span: Span { start: 0, end: 0 },
+        };
+        match members.iter_mut().find(|m| &m.key == key) {
+            Some(member) => member.value = value,
+            None => members.push(Member {
+                key: key.clone(),
+                key_span: Span { start: 0, end: 0 },
+                value,
+            }),
+        }
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)?
+        .write_all((json::to_pretty_string(&document, "  ") + "\n").as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_missing_keys() {
+        let contents = r#"{"USER": "TO_BE_OVERWRITTEN", "TOKEN": {"$value": null, "required": true}, "URL": "x"}"#;
+        let config = Config::test("a.json", contents);
+        assert_eq!(missing_keys(&config), vec!["TOKEN", "USER"]);
+    }
+
+    #[test]
+    fn saves_answers() {
+        let path =
+            std::env::temp_dir().join(format!("json_env_prompt_test_{}.json", std::process::id()));
+        fs::write(&path, "{\"B\": \"old\", \"A\": 1}").unwrap();
+        let answers = BTreeMap::from([
+            ("B".to_string(), "new".to_string()),
+            ("C".to_string(), "c".to_string()),
+        ]);
+        save(&path, &answers).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"B\": \"new\",\n  \"A\": 1,\n  \"C\": \"c\"\n}\n"
+        );
+        fs::remove_file(&path).unwrap();
+        save(&path, &answers).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"B\": \"new\",\n  \"C\": \"c\"\n}\n"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+//! Values that are looked up in secret stores by external commands.
+//!
+//! `{"$cmd": ["pass", "show", "db"]}` runs a command and uses what it prints, without the final newline.
+//! A string like `"vault:secret/db#password"` is passed to the helper that is configured for `vault` in
+//! `~/.config/json_env/resolvers.json`. The helper gets `{"resolver": "vault", "reference": "secret/db#password"}`
+//! on stdin and prints `{"value": "..."}` or `{"error": "..."}`. Each command is run at most once per run of
+//! json_env, and is killed if it takes longer than its timeout.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::config_dir;
+use crate::loader::{self, Layer};
+
+/// Key of an object whose value is the output of a command.
+pub const CMD_KEY: &str = "$cmd";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A configured helper executable for named references.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Helper {
+    pub command: Vec<String>,
+    pub timeout: Duration,
+}
+
+/// The configured helpers and the values they returned so far.
+#[derive(Debug, Default)]
+pub struct Resolvers {
+    helpers: BTreeMap<String, Helper>,
+    cache: HashMap<(Vec<String>, String), String>,
+}
+
+/// The file in which helpers are configured.
+pub fn config_file() -> Option<PathBuf> {
+    config_dir::file("resolvers.json")
+}
+
+impl Resolvers {
+    pub fn new(helpers: BTreeMap<String, Helper>) -> Resolvers {
+        Resolvers {
+            helpers,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// The helpers configured in `~/.config/json_env/resolvers.json`, e.g.
+    /// `{"vault": {"command": ["json_env-vault"], "timeout": 5}}`. The timeout is in seconds.
+    pub fn from_config() -> Result<Resolvers> {
+        let Some(file) = config_file().filter(|file| file.exists()) else {
+            return Ok(Resolvers::default());
+        };
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Could not read '{}'", file.display()))?;
+        let config: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("'{}' is not valid JSON", file.display()))?;
+        let Some(config) = config.as_object() else {
+            bail!("'{}' must contain an object", file.display());
+        };
+        let mut helpers = BTreeMap::new();
+        for (name, helper) in config {
+            let command = helper.get("command").and_then(string_list).ok_or_else(|| {
+                anyhow!(
+                    "the resolver '{name}' needs a \"command\" list in '{}'",
+                    file.display()
+                )
+            })?;
+            helpers.insert(
+                name.clone(),
+                Helper {
+                    command,
+                    timeout: timeout(helper),
+                },
+            );
+        }
+        Ok(Resolvers::new(helpers))
+    }
+
+    /// The keys in `layers` whose values are `$cmd` objects or named references, i.e. that run a command when they
+    /// are resolved.
+    pub fn references(&self, layers: &[Layer]) -> Vec<String> {
+        let mut references = Vec::new();
+        for layer in layers {
+            let Some(object) = layer.value.as_object() else {
+                continue;
+            };
+            for (key, value) in object {
+                let value = match loader::definition(value) {
+                    Some(definition) => &definition[loader::VALUE_KEY],
+                    None => value,
+                };
+                if self.is_reference(value) && !references.contains(key) {
+                    references.push(key.clone());
+                }
+            }
+        }
+        references
+    }
+
+    fn is_reference(&self, value: &Value) -> bool {
+        value.get(CMD_KEY).is_some()
+            || value
+                .as_str()
+                .and_then(|s| s.split_once(':'))
+                .is_some_and(|(name, _)| self.helpers.contains_key(name))
+    }
+
+    /// Replace `$cmd` objects and named references in `layers` with the values they resolve to. Returns the
+    /// keys that were resolved.
+    pub fn resolve_layers(&mut self, layers: &mut [Layer]) -> Result<Vec<String>> {
+        let mut resolved = Vec::new();
+        for layer in layers {
+            let file = layer.file.clone();
+            for (key, value) in loader::values_mut(layer) {
+                let result = self
+                    .resolve(value)
+                    .with_context(|| format!("Could not resolve '{key}' in '{file}'"))?;
+                if let Some(result) = result {
+                    *value = Value::String(result);
+                    resolved.push(key.clone());
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// The value that `value` refers to, or `None` if it is not a reference.
+    fn resolve(&mut self, value: &Value) -> Result<Option<String>> {
+        if let Some(command) = value.get(CMD_KEY) {
+            let command = string_list(command)
+                .filter(|command| !command.is_empty())
+                .ok_or_else(|| anyhow!("\"{CMD_KEY}\" must be a list of strings"))?;
+            let output = self.run_cached(&command, "", timeout(value))?;
+            return Ok(Some(output.trim_end_matches(['\n', '\r']).to_string()));
+        }
+        let Some((name, reference)) = value.as_str().and_then(|s| s.split_once(':')) else {
+            return Ok(None);
+        };
+        let Some(helper) = self.helpers.get(name).cloned() else {
+            return Ok(None);
+        };
+        let request = json!({ "resolver": name, "reference": reference }).to_string();
+        let output = self.run_cached(&helper.command, &request, helper.timeout)?;
+        let response: Value = serde_json::from_str(&output)
+            .with_context(|| format!("the resolver '{name}' did not print valid JSON"))?;
+        if let Some(error) = response.get("error") {
+            bail!(
+                "the resolver '{name}' failed: {}",
+                loader::to_env_string(error)
+            );
+        }
+        match response.get("value") {
+            Some(value) => Ok(Some(loader::to_env_string(value))),
+            None => bail!("the resolver '{name}' did not return a \"value\""),
+        }
+    }
+
+    fn run_cached(&mut self, command: &[String], input: &str, timeout: Duration) -> Result<String> {
+        let key = (command.to_vec(), input.to_string());
+        if let Some(output) = self.cache.get(&key) {
+            return Ok(output.clone());
+        }
+        let output = run(command, input, timeout)?;
+        self.cache.insert(key, output.clone());
+        Ok(output)
+    }
+}
+
+fn string_list(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|item| item.as_str().map(str::to_string))
+        .collect()
+}
+
+/// The `"timeout"` of a helper or `$cmd` object in seconds, or the default.
+fn timeout(value: &Value) -> Duration {
+    value
+        .get("timeout")
+        .and_then(Value::as_f64)
+        .filter(|seconds| *seconds > 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Run `command` with `input` on stdin and return what it prints on stdout.
+fn run(command: &[String], input: &str, timeout: Duration) -> Result<String> {
+    let shown = command.join(" ");
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("could not start '{shown}'"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Commands that do not read their input close it early, which is fine
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let read = |mut stream: Box<dyn Read + Send>| {
+        thread::spawn(move || {
+            let mut text = String::new();
+            let _ = stream.read_to_string(&mut text);
+            text
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read(Box::new(child.stderr.take().expect("stderr is piped")));
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "'{shown}' did not finish within {} seconds",
+                timeout.as_secs_f64()
+            );
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        bail!("'{shown}' exited with {status}: {}", stderr.trim());
+    }
+    Ok(stdout)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::loader::Options;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn runs_commands_and_helpers() {
+        let counter =
+            std::env::temp_dir().join(format!("json_env_resolver_count_{}", std::process::id()));
+        let script = format!(
+            "echo x >> {}; read request; echo '{{\"value\": \"s3cret\"}}'",
+            counter.display()
+        );
+        let helper = Helper {
+            command: sh(&script),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let failing = Helper {
+            command: sh("echo '{\"error\": \"access denied\"}'"),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let mut resolvers = Resolvers::new(BTreeMap::from([
+            ("vault".to_string(), helper),
+            ("op".to_string(), failing),
+        ]));
+        let contents = r#"{"A": {"$cmd": ["echo", "hello"]}, "B": "vault:secret/db#password", "C": {"$value": "vault:secret/db#password"}, "D": "https://example.com"}"#;
+        let mut layers =
+            loader::parse_layers("a.json", contents, "$", &Options::default()).unwrap();
+        assert_eq!(resolvers.references(&layers), vec!["A", "B", "C"]);
+        let resolved = resolvers.resolve_layers(&mut layers).unwrap();
+        assert_eq!(resolved, vec!["A", "B", "C"]);
+        assert_eq!(layers[0].value["A"], "hello");
+        assert_eq!(layers[0].value["B"], "s3cret");
+        assert_eq!(layers[0].value["C"]["$value"], "s3cret");
+        assert_eq!(layers[0].value["D"], "https://example.com");
+        // The helper ran only once for both references
+        assert_eq!(fs::read_to_string(&counter).unwrap(), "x\n");
+        fs::remove_file(&counter).unwrap();
+
+        let error = resolvers.resolve(&json!("op:item")).unwrap_err();
+        assert_eq!(error.to_string(), "the resolver 'op' failed: access denied");
+    }
+
+    #[test]
+    fn times_out() {
+        let mut resolvers = Resolvers::default();
+        let value = json!({"$cmd": ["sleep", "5"], "timeout": 0.1});
+        let started = Instant::now();
+        let error = resolvers.resolve(&value).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("did not finish within 0.1 seconds"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
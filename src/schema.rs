@@ -0,0 +1,372 @@
+//! Validating the merged variables against a JSON schema.
+//!
+//! The variables are treated as one object whose values are strings. Type keywords check whether a
+//! string can be read as that type, e.g. `"type": "integer"` accepts `"8080"`. The supported keywords are
+//! `required`, `properties`, `additionalProperties`, `type`, `enum`, `const`, `pattern`, `format`
+//! (`uri`, `email`, `hostname`, `ipv4`), `minLength`, `maxLength`, `minimum`, `maximum`,
+//! `exclusiveMinimum` and `exclusiveMaximum`. Other keywords are ignored.
+
+use std::fmt::{Display, Formatter};
+use std::net::Ipv4Addr;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::change::Change;
+use crate::loader::{Config, Var};
+use crate::secret;
+
+/// A variable that does not satisfy the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub key: String,
+    pub message: String,
+    /// Where the offending value was defined or the variable was declared.
+    pub source: Option<String>,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, " ({source})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the variables of `config` against `schema` and return every violation. Variables that are unset count
+/// as not defined, and the values of secrets are masked in the messages.
+pub fn validate(schema: &Value, config: &Config) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let vars: Vec<(&String, &Var)> = config
+        .vars
+        .iter()
+        .filter(|(_, var)| var.change != Change::Unset)
+        .collect();
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !vars.iter().any(|(defined, _)| *defined == key) {
+                violations.push(Violation {
+                    key: key.to_string(),
+                    message: "is required but not defined".to_string(),
+                    source: None,
+                });
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, var) in vars {
+        let property = properties.and_then(|p| p.get(key.as_str()));
+        let property = match (property, schema.get("additionalProperties")) {
+            (Some(property), _) => property,
+            (None, Some(Value::Bool(false))) => {
+                violations.push(violation(
+                    key,
+                    var,
+                    "is not allowed by the schema".to_string(),
+                ));
+                continue;
+            }
+            (None, Some(additional)) => additional,
+            (None, None) => continue,
+        };
+        for message in check_value(property, &var.value, config.is_secret(key)) {
+            violations.push(violation(key, var, message));
+        }
+    }
+    violations
+}
+
+/// Check the variables of `config` against the `required` and `type` fields of their definitions. Variables
+/// that are unset count as not defined, and the values of secrets are masked in the messages.
+pub fn validate_meta(config: &Config) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (key, meta) in &config.meta {
+        match config
+            .vars
+            .get(key)
+            .filter(|var| var.change != Change::Unset)
+        {
+            None if meta.required => violations.push(Violation {
+                key: key.clone(),
+                message: "is required but not defined".to_string(),
+                source: meta.source.as_ref().map(|s| format!("declared at {s}")),
+            }),
+            None => {}
+            Some(var) => {
+                if let Some(value_type) = &meta.value_type {
+                    let schema = serde_json::json!({ "type": value_type });
+                    for message in check_value(&schema, &var.value, config.is_secret(key)) {
+                        violations.push(violation(key, var, message));
+                    }
+                }
+            }
+        }
+    }
+    violations
+}
+
+fn violation(key: &str, var: &Var, message: String) -> Violation {
+    Violation {
+        key: key.to_string(),
+        message,
+        source: Some(format!("defined at {}", var.source)),
+    }
+}
+
+type Comparison = fn(f64, f64) -> bool;
+
+/// Check a single value against the schema of its property. The messages show a mask instead of the value if
+/// it is `secret`.
+fn check_value(schema: &Value, value: &str, secret: bool) -> Vec<String> {
+    let mut messages = Vec::new();
+    let shown = if secret { secret::MASK } else { value };
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            messages.push(format!("'{shown}' is not of type {}", types.join(" or ")));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.iter().any(|a| matches_json(value, a)) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            messages.push(format!("'{shown}' is not one of {}", allowed.join(", ")));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if !matches_json(value, constant) {
+            messages.push(format!("'{shown}' is not {constant}"));
+        }
+    }
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        match Regex::new(pattern) {
+            Ok(regex) if !regex.is_match(value) => {
+                messages.push(format!("'{shown}' does not match the pattern '{pattern}'"))
+            }
+            Ok(_) => {}
+            Err(e) => messages.push(format!("the schema has an invalid pattern: {e}")),
+        }
+    }
+    if let Some(format) = schema.get("format").and_then(Value::as_str) {
+        if !has_format(value, format) {
+            messages.push(format!("'{shown}' is not a valid {format}"));
+        }
+    }
+    let length = value.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+        if length < min {
+            messages.push(format!("'{shown}' is shorter than {min} characters"));
+        }
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+        if length > max {
+            messages.push(format!("'{shown}' is longer than {max} characters"));
+        }
+    }
+    if let Ok(number) = value.trim().parse::<f64>() {
+        let bounds: [(&str, &str, Comparison); 4] = [
+            ("minimum", "less than", |n, b| n < b),
+            ("maximum", "greater than", |n, b| n > b),
+            ("exclusiveMinimum", "less than or equal to", |n, b| n <= b),
+            ("exclusiveMaximum", "greater than or equal to", |n, b| {
+                n >= b
+            }),
+        ];
+        for (keyword, relation, violated) in bounds {
+            match schema.get(keyword).and_then(Value::as_f64) {
+                Some(bound) if violated(number, bound) => {
+                    messages.push(format!("{shown} is {relation} {bound}"))
+                }
+                _ => {}
+            }
+        }
+    }
+    messages
+}
+
+/// Whether the string `value` can be read as the JSON schema type `t`.
+fn has_type(value: &str, t: &str) -> bool {
+    match t {
+        "string" => true,
+        "integer" => value.parse::<i64>().is_ok() || value.parse::<u64>().is_ok(),
+        "number" => value.parse::<f64>().map(f64::is_finite).unwrap_or(false),
+        "boolean" => value == "true" || value == "false",
+        "null" => value == "null",
+        "array" => matches!(serde_json::from_str(value), Ok(Value::Array(_))),
+        "object" => matches!(serde_json::from_str(value), Ok(Value::Object(_))),
+        _ => true,
+    }
+}
+
+/// Whether the string `value` equals the JSON value `expected`, comparing strings without quotes.
+fn matches_json(value: &str, expected: &Value) -> bool {
+    match expected {
+        Value::String(s) => s == value,
+        other => serde_json::from_str::<Value>(value).ok().as_ref() == Some(other),
+    }
+}
+
+fn has_format(value: &str, format: &str) -> bool {
+    let pattern = match format {
+        "uri" => r"^[a-zA-Z][a-zA-Z0-9+.-]*:[^\s]+$",
+        "email" => r"^[^@\s]+@[^@\s]+\.[^@\s]+$",
+        "hostname" => {
+            r"^([a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"
+        }
+        "ipv4" => return value.parse::<Ipv4Addr>().is_ok(),
+        // Unknown formats are not checked
+        _ => return true,
+    };
+    Regex::new(pattern).unwrap().is_match(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{Meta, Vars};
+    use serde_json::json;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vars {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), Var::test(value, ".env.json", Some(2))))
+            .collect()
+    }
+
+    fn config(vars: Vars) -> Config {
+        Config {
+            vars,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let schema = json!({
+            "required": ["DATABASE_URL", "PORT"],
+            "properties": {
+                "DATABASE_URL": {"type": "string", "format": "uri"},
+                "PORT": {"type": "integer", "minimum": 1, "maximum": 65535},
+                "LOG_LEVEL": {"enum": ["debug", "info"]}
+            },
+            "additionalProperties": false
+        });
+        let mut vars = vars(&[
+            ("DATABASE_URL", ""),
+            ("PORT", "70000"),
+            ("LOG_LEVEL", "trace"),
+            ("EXTRA", "1"),
+        ]);
+        vars.get_mut("DATABASE_URL").unwrap().change = Change::Unset;
+        let violations = validate(&schema, &config(vars));
+        let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "DATABASE_URL: is required but not defined",
+                "EXTRA: is not allowed by the schema (defined at .env.json:2 ($))",
+                "LOG_LEVEL: 'trace' is not one of \"debug\", \"info\" (defined at .env.json:2 ($))",
+                "PORT: 70000 is greater than 65535 (defined at .env.json:2 ($))",
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let mut config = Config::default();
+        config.meta.insert(
+            "TOKEN".to_string(),
+            Meta {
+                required: true,
+                ..Meta::default()
+            },
+        );
+        config.meta.insert(
+            "PORT".to_string(),
+            Meta {
+                value_type: Some("integer".to_string()),
+                ..Meta::default()
+            },
+        );
+        config.meta.insert(
+            "PIN".to_string(),
+            Meta {
+                secret: true,
+                value_type: Some("integer".to_string()),
+                ..Meta::default()
+            },
+        );
+        config.vars = vars(&[("PIN", "hunter2"), ("PORT", "eighty"), ("TOKEN", "")]);
+        config.vars.get_mut("TOKEN").unwrap().change = Change::Unset;
+        let messages: Vec<String> = validate_meta(&config)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "PIN: '****' is not of type integer (defined at .env.json:2 ($))",
+                "PORT: 'eighty' is not of type integer (defined at .env.json:2 ($))",
+                "TOKEN: is required but not defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_values() {
+        let schema = json!({
+            "properties": {
+                "DATABASE_URL": {"type": "string", "pattern": "^postgres://"},
+                "PORT": {"type": "integer"},
+                "DEBUG": {"type": "boolean"}
+            }
+        });
+        let vars = vars(&[
+            ("DATABASE_URL", "postgres://localhost/db"),
+            ("PORT", "8080"),
+            ("DEBUG", "false"),
+        ]);
+        assert!(validate(&schema, &config(vars)).is_empty());
+    }
+
+    #[test]
+    fn masks_secrets() {
+        let schema = json!({
+            "properties": {
+                "DB_PASSWORD": {"type": "integer", "minLength": 10},
+                "API_KEY": {"type": "integer", "maximum": 10},
+                "PORT": {"type": "integer"}
+            }
+        });
+        let mut config = config(vars(&[
+            ("DB_PASSWORD", "hunter2"),
+            ("API_KEY", "42"),
+            ("PORT", "eighty"),
+        ]));
+        config.meta.insert(
+            "API_KEY".to_string(),
+            Meta {
+                secret: true,
+                ..Meta::default()
+            },
+        );
+        let messages: Vec<String> = validate(&schema, &config)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "API_KEY: **** is greater than 10 (defined at .env.json:2 ($))",
+                "DB_PASSWORD: '****' is not of type integer (defined at .env.json:2 ($))",
+                "DB_PASSWORD: '****' is shorter than 10 characters (defined at .env.json:2 ($))",
+                "PORT: 'eighty' is not of type integer (defined at .env.json:2 ($))",
+            ]
+        );
+    }
+}
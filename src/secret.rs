@@ -0,0 +1,36 @@
+//! Deciding which values must not be shown.
+
+/// Parts of key names that usually hold secrets, compared case-insensitively.
+const SECRET_WORDS: &[&str] = &[
+    "PASSWORD",
+    "PASSWD",
+    "SECRET",
+    "TOKEN",
+    "PRIVATE",
+    "CREDENTIAL",
+    "API_KEY",
+    "APIKEY",
+    "ACCESS_KEY",
+];
+
+pub const MASK: &str = "****";
+
+/// `true` if the name of a key suggests that its value is a secret.
+pub fn looks_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    SECRET_WORDS.iter().any(|word| key.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_looking_keys() {
+        assert!(looks_secret("DB_PASSWORD"));
+        assert!(looks_secret("github_token"));
+        assert!(looks_secret("STRIPE_API_KEY"));
+        assert!(!looks_secret("NODE_ENV"));
+        assert!(!looks_secret("KEYBOARD_LAYOUT"));
+    }
+}
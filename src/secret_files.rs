@@ -0,0 +1,121 @@
+//! Passing secrets to the child process as files instead of environment variables, like Docker secrets:
+//! `DB_PASSWORD` becomes `DB_PASSWORD_FILE=/run/user/1000/json_env-123/DB_PASSWORD`.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::{env, process};
+
+use crate::change::Change;
+use crate::loader::{Var, Vars};
+
+/// A private directory with one file per secret. It is removed when this is dropped.
+#[derive(Debug)]
+pub struct SecretFiles {
+    pub directory: PathBuf,
+}
+
+impl SecretFiles {
+    /// Write the values of `keys` to files and add `<KEY>_FILE` variables with the paths to `vars`. The keys
+    /// themselves are unset, so that the program does not inherit them from the environment either. Variables
+    /// that are unset get no file, those that are prepended or appended to get a file with the whole value.
+    pub fn write(vars: &mut Vars, keys: &[String]) -> io::Result<SecretFiles> {
+        let directory = base_directory().join(format!("json_env-{}", process::id()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&directory)?;
+        let files = SecretFiles { directory };
+        for key in keys {
+            let Some(var) = vars.get_mut(key).filter(|var| var.change != Change::Unset) else {
+                continue;
+            };
+            let path = files.directory.join(key);
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(&path)?.write_all(var.value.as_bytes())?;
+            let file_var = Var {
+                value: path.to_string_lossy().to_string(),
+                source: var.source.clone(),
+                unexpanded: None,
+                overridden: Vec::new(),
+                change: Change::Set,
+            };
+            var.value = String::new();
+            var.change = Change::Unset;
+            vars.insert(format!("{key}_FILE"), file_var);
+        }
+        Ok(files)
+    }
+}
+
+impl Drop for SecretFiles {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+/// A directory that is kept in memory if possible: `$XDG_RUNTIME_DIR` or `/dev/shm`, else the temp directory.
+fn base_directory() -> PathBuf {
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        if runtime.is_dir() {
+            return runtime;
+        }
+    }
+    let shm = PathBuf::from("/dev/shm");
+    if shm.is_dir() {
+        return shm;
+    }
+    env::temp_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_and_removes_files() {
+        let mut vars = Vars::new();
+        for (key, value) in [("DB_PASSWORD", "hunter2"), ("USER", "admin")] {
+            vars.insert(key.to_string(), Var::test(value, ".env.json", None));
+        }
+        let files = SecretFiles::write(&mut vars, &["DB_PASSWORD".to_string()]).unwrap();
+        let keys: Vec<&String> = vars.keys().collect();
+        assert_eq!(keys, vec!["DB_PASSWORD", "DB_PASSWORD_FILE", "USER"]);
+        assert_eq!(vars["DB_PASSWORD"].change, Change::Unset);
+        let path = PathBuf::from(&vars["DB_PASSWORD_FILE"].value);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hunter2");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let directory = files.directory.clone();
+        drop(files);
+        assert!(!directory.exists());
+
+        // The directory is named after the process, so the cases cannot run as separate tests
+        let mut vars = Vars::new();
+        let mut unset = Var::test("", ".env.json", None);
+        unset.change = Change::Unset;
+        vars.insert("OLD_TOKEN".to_string(), unset);
+        // The value of a prepended variable already includes the inherited one
+        let mut extended = Var::test("./keys:/etc/keys", ".env.json", None);
+        extended.change = Change::Extend {
+            before: vec!["./keys".to_string()],
+            after: Vec::new(),
+            sep: ":".to_string(),
+        };
+        vars.insert("KEY_PATH".to_string(), extended);
+        let keys = ["OLD_TOKEN".to_string(), "KEY_PATH".to_string()];
+        let _files = SecretFiles::write(&mut vars, &keys).unwrap();
+        let keys: Vec<&String> = vars.keys().collect();
+        assert_eq!(keys, vec!["KEY_PATH", "KEY_PATH_FILE", "OLD_TOKEN"]);
+        let path = PathBuf::from(&vars["KEY_PATH_FILE"].value);
+        assert_eq!(fs::read_to_string(path).unwrap(), "./keys:/etc/keys");
+        assert_eq!(vars["KEY_PATH"].change, Change::Unset);
+    }
+}
@@ -0,0 +1,161 @@
+//! Starting an interactive shell with the variables set, for `json_env shell`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+/// Set in the shell to the config file it was started for, so that shells are not nested by accident.
+pub const ACTIVE_VARIABLE: &str = "JSON_ENV_ACTIVE";
+
+/// The shell of the user: `$SHELL`, or `%COMSPEC%` on Windows.
+pub fn user_shell() -> String {
+    #[cfg(windows)]
+    let fallback = env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+    #[cfg(not(windows))]
+    let fallback = "/bin/sh".to_string();
+    env::var("SHELL").unwrap_or(fallback)
+}
+
+/// How to start a shell, with the files it needs to change its prompt. They are in a private directory that is
+/// removed when this is dropped.
+#[derive(Debug)]
+pub struct Launch {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Variables that the shell needs besides those from the config files.
+    pub env: Vec<(String, String)>,
+    directory: Option<PathBuf>,
+}
+
+impl Launch {
+    /// Start `program` as it is.
+    pub fn plain(program: &str) -> Launch {
+        Launch {
+            program: program.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            directory: None,
+        }
+    }
+
+    /// Start `program` so that its prompt begins with `prefix`, after the user's own configuration has set it.
+    /// Returns `None` for shells whose prompt cannot be changed.
+    pub fn with_prompt(program: &str, prefix: &str) -> io::Result<Option<Launch>> {
+        let name = Path::new(program)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let mut launch = Launch::plain(program);
+        match name.as_str() {
+            "bash" => {
+                let directory = launch.create_directory()?;
+                let rc_file = directory.join("bashrc");
+                let script = format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\nPS1={}\"$PS1\"\n",
+                    quote(prefix)
+                );
+                fs::write(&rc_file, script)?;
+                launch.args = vec![
+                    "--rcfile".to_string(),
+                    rc_file.to_string_lossy().to_string(),
+                    "-i".to_string(),
+                ];
+            }
+            "zsh" => {
+                // zsh reads its startup files from $ZDOTDIR, so it is pointed to files that read the user's
+                // files first and then put it back
+                let user_directory = env::var("ZDOTDIR").ok();
+                let home = dirs::home_dir().unwrap_or_default();
+                let user_files = user_directory
+                    .clone()
+                    .unwrap_or_else(|| home.to_string_lossy().to_string());
+                let restore = match &user_directory {
+                    Some(directory) => format!("ZDOTDIR={}", quote(directory)),
+                    None => "unset ZDOTDIR".to_string(),
+                };
+                let directory = launch.create_directory()?;
+                let source = |file: &str| {
+                    let path = quote(&format!("{user_files}/{file}"));
+                    format!("[ -f {path} ] && . {path}\n")
+                };
+                fs::write(directory.join(".zshenv"), source(".zshenv"))?;
+                let script = format!(
+                    "{}{restore}\nPS1={}\"$PS1\"\n",
+                    source(".zshrc"),
+                    quote(prefix)
+                );
+                fs::write(directory.join(".zshrc"), script)?;
+                launch.env.push((
+                    "ZDOTDIR".to_string(),
+                    directory.to_string_lossy().to_string(),
+                ));
+            }
+            "fish" => {
+                let command = format!(
+                    "functions -c fish_prompt _json_env_fish_prompt; function fish_prompt; echo -n {}; _json_env_fish_prompt; end",
+                    quote(prefix)
+                );
+                launch.args = vec!["--init-command".to_string(), command];
+            }
+            "cmd" => {
+                let prompt = env::var("PROMPT").unwrap_or_else(|_| "$P$G".to_string());
+                launch
+                    .env
+                    .push(("PROMPT".to_string(), format!("{prefix}{prompt}")));
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(launch))
+    }
+
+    fn create_directory(&mut self) -> io::Result<PathBuf> {
+        let directory = env::temp_dir().join(format!("json_env-shell-{}", process::id()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&directory)?;
+        self.directory = Some(directory.clone());
+        Ok(directory)
+    }
+}
+
+impl Drop for Launch {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.directory {
+            let _ = fs::remove_dir_all(directory);
+        }
+    }
+}
+
+/// `s` in single quotes, for POSIX shells and fish.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_prompts() {
+        let launch = Launch::with_prompt("/bin/bash", "(json_env) ")
+            .unwrap()
+            .unwrap();
+        assert_eq!(launch.args[0], "--rcfile");
+        let rc_file = PathBuf::from(&launch.args[1]);
+        let script = fs::read_to_string(&rc_file).unwrap();
+        assert!(script.ends_with("PS1='(json_env) '\"$PS1\"\n"));
+        drop(launch);
+        assert!(!rc_file.exists());
+
+        let launch = Launch::with_prompt("/usr/bin/fish", "(json_env) ")
+            .unwrap()
+            .unwrap();
+        assert!(launch.args[1].contains("echo -n '(json_env) '"));
+        assert!(Launch::with_prompt("/bin/tcsh", "(json_env) ")
+            .unwrap()
+            .is_none());
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
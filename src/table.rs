@@ -0,0 +1,45 @@
+//! Plain text tables with aligned columns.
+
+/// Render a table with a header row. Columns are separated by two spaces, the last column is not padded.
+pub fn render(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let header: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                let padding = widths[i] - cell.chars().count();
+                line.push_str(cell);
+                line.push_str(&" ".repeat(padding + 2));
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_columns() {
+        let rows = vec![
+            vec!["A".to_string(), "1".to_string(), "x".to_string()],
+            vec!["LONGER".to_string(), "".to_string(), "y".to_string()],
+        ];
+        assert_eq!(
+            render(&["KEY", "VALUE", "SOURCE"], &rows),
+            "KEY     VALUE  SOURCE\nA       1      x\nLONGER         y\n"
+        );
+    }
+}